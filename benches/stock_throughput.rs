@@ -0,0 +1,76 @@
+//! Compares the throughput of local purchases in a shop with its whole stock in a single
+//! shard, as it was before the stock was split, against the stock split in the default
+//! `stock_shards` of the configuration.
+//! Every stock change is written to the inventory log, as the shop binary does.
+//!
+//! Run it with `cargo bench --bench stock_throughput`.
+
+use std::time::{Duration, Instant};
+
+use actix::Actor;
+use concurrentes::config::Config;
+use concurrentes::constants::PURCHASE_MILLIS;
+use concurrentes::shop::local_purchase::LocalPurchase;
+use concurrentes::shop::shop_actor::{Product, Shop};
+use concurrentes::states::LocalPurchaseState;
+use futures::future::join_all;
+
+const PRODUCTS: usize = 16;
+const PURCHASES: usize = 4000;
+
+/// Sends every purchase to a new shop with the given number of shards at once, and
+/// returns how long it took to process them
+async fn run(shards: usize) -> Duration {
+    let dir = std::env::temp_dir().join(format!("inventario-bench-{}", shards));
+    let _ = std::fs::remove_dir_all(&dir);
+
+    let products = (0..PRODUCTS)
+        .map(|product| Product {
+            id: format!("producto{}", product),
+            stock: PURCHASES as u32,
+            reserved: 0,
+        })
+        .collect();
+    let mut shop = Shop::new("bench", "localhost:0", 1, products);
+    shop.config.stock_shards = shards;
+    shop.inventory_path = Some(dir.to_string_lossy().to_string());
+    let shop = shop.start();
+
+    let purchases = (0..PURCHASES).map(|purchase| LocalPurchase {
+        product: format!("producto{}", purchase % PRODUCTS),
+        quantity: 1,
+        status: LocalPurchaseState::CREATED,
+    });
+
+    let start = Instant::now();
+    let results = join_all(purchases.map(|purchase| shop.send(purchase))).await;
+    let elapsed = start.elapsed();
+
+    let sold = results
+        .into_iter()
+        .filter(|result| matches!(result, Ok(Ok(LocalPurchaseState::SOLD))))
+        .count();
+    assert_eq!(sold, PURCHASES);
+
+    let _ = std::fs::remove_dir_all(&dir);
+    elapsed
+}
+
+fn main() {
+    let system = actix::System::new();
+    system.block_on(async {
+        println!(
+            "{} compras locales de {} productos (cada compra espera {} ms)",
+            PURCHASES, PRODUCTS, PURCHASE_MILLIS
+        );
+        for shards in [1, Config::default().stock_shards] {
+            let elapsed = run(shards).await;
+            println!(
+                "{:>2} shard(s): {:>8.2?} -> {:>8.0} compras/s",
+                shards,
+                elapsed,
+                PURCHASES as f64 / elapsed.as_secs_f64()
+            );
+        }
+    });
+}
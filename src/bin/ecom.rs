@@ -1,27 +1,44 @@
 use actix::{Actor, System};
+use concurrentes::config::Config;
 use concurrentes::ecom::connect_shops::ConnectShops;
+use concurrentes::ecom::connected_shops::ConnectedShop;
 use concurrentes::ecom::connection_handling::connection_handling;
 use concurrentes::ecom::ecom_actor::Ecom;
+use concurrentes::ecom::journal::Journal;
 use concurrentes::ecom::process_ecom_orders::ProcessEcomOrders;
+use concurrentes::ecom::resume_orders::ResumeOrders;
+use concurrentes::ecom::routing::{routing_from_name, NearestRouting, RoutingStrategy};
 use concurrentes::error::FileError;
+use concurrentes::zones::ZoneMap;
 // use concurrentes::messages::process_orders::ProcessOrders;
 use std::io::{stdin, stdout, Write};
 use std::{env, path::Path};
 
 const CANT_ARGS: usize = 2;
+const ROUTING_FLAG: &str = "--routing";
+const RECOVER_FLAG: &str = "--recover";
 
 fn main() {
     let system = System::new();
 
     system.block_on(async {
-        let path = match get_args() {
+        let args: Vec<String> = env::args().collect();
+        let config = match Config::from_args(&args) {
+            Ok(config) => config,
+            Err(error) => {
+                println!("ERROR leyendo configuracion: {:?}", error);
+                System::current().stop();
+                return;
+            }
+        };
+        let (path, routing, recover) = match get_args(&args, &config) {
             Ok(args) => args,
             Err(_) => {
                 System::current().stop();
                 return;
             }
         };
-        let ecom: Ecom = match Ecom::from_file(path.as_str()) {
+        let mut ecom: Ecom = match Ecom::from_file(path.as_str()) {
             Ok(ecom) => ecom,
             Err(error) => {
                 println!("ERROR ecom: {:?}", error);
@@ -29,6 +46,79 @@ fn main() {
                 return;
             }
         };
+        ecom.routing = routing;
+        ecom.zones = match ZoneMap::from_optional_file(&config.zones_path) {
+            Ok(zones) => zones,
+            Err(error) => {
+                println!("ERROR leyendo zonas: {:?}", error);
+                System::current().stop();
+                return;
+            }
+        };
+
+        // con --recover se retoman los pedidos del diario, si no se empieza uno nuevo
+        let journal_path = Journal::path_for(&config.journals_path, &ecom.name);
+        let mut created = Default::default();
+        if recover {
+            match Journal::replay(&journal_path, &ecom.name) {
+                Ok(replay) => {
+                    ecom.pending_orders = replay.pending_orders;
+                    created = replay.created;
+                }
+                Err(error) => {
+                    println!("ERROR leyendo diario: {:?}", error);
+                    System::current().stop();
+                    return;
+                }
+            }
+        }
+        let journal = if recover {
+            Journal::open(&journal_path)
+        } else {
+            Journal::create(&journal_path)
+        };
+        ecom.journal = match journal {
+            Ok(journal) => Some(journal),
+            Err(error) => {
+                println!("ERROR abriendo diario: {:?}", error);
+                System::current().stop();
+                return;
+            }
+        };
+        let orders: Vec<_> = match Ecom::orders_from_file(path.as_str()) {
+            Ok(orders) => orders
+                .into_iter()
+                .filter(|order| !created.contains(&order.id))
+                .collect(),
+            Err(error) => {
+                println!("ERROR obteniendo orders: {:?}", error);
+                System::current().stop();
+                return;
+            }
+        };
+
+        // una zona que falta en el mapa perderia todas sus entregas
+        let shop_zones: Vec<i32> = match ConnectedShop::from_file(&config.shops_path) {
+            Ok(shops) => shops.iter().map(|(_, zone_id, _)| *zone_id).collect(),
+            Err(error) => {
+                println!("ERROR leyendo tiendas: {:?}", error);
+                System::current().stop();
+                return;
+            }
+        };
+        let order_zones: Vec<i32> = orders
+            .iter()
+            .chain(ecom.pending_orders.values())
+            .map(|order| order.zone_id)
+            .collect();
+        if let Err(error) = ecom.zones.check_coverage(&shop_zones, &order_zones) {
+            println!("ERROR en el mapa de zonas: {:?}", error);
+            System::current().stop();
+            return;
+        }
+
+        ecom.rng = config.rng();
+        ecom.config = config;
         let ecom = ecom.start();
 
         start_on_enter();
@@ -39,17 +129,12 @@ fn main() {
             return;
         };
 
-        let orders = match Ecom::orders_from_file(path.as_str()) {
-            Ok(orders) => orders,
-            Err(error) => {
-                println!("ERROR obteniendo orders: {:?}", error);
-                System::current().stop();
-                return;
-            }
-        };
-
         connection_handling(ecom.clone());
 
+        if recover {
+            ecom.do_send(ResumeOrders);
+        }
+
         if let Err(error) = ecom.send(ProcessEcomOrders(orders)).await {
             println!("ERROR procesando ordenes: {:?}", error);
             System::current().stop()
@@ -60,21 +145,45 @@ fn main() {
     }
 }
 
-/// Gets the path from the ecom file from the command line arguments
-fn get_args() -> Result<String, FileError> {
-    let args: Vec<String> = env::args().collect();
+/// Gets the path from the ecom file, in the orders directory of the configuration, the
+/// routing strategy and whether to recover the orders from the journal from the command
+/// line arguments
+fn get_args(
+    args: &[String],
+    config: &Config,
+) -> Result<(String, Box<dyn RoutingStrategy>, bool), FileError> {
     if args.len() < CANT_ARGS {
         println!("ERROR: ecom file not provided");
         return Err(FileError::NotFound);
     }
 
-    let path = format!("pedidos/{}.txt", &args[1]);
+    let path = format!("{}/{}.txt", config.orders_path, &args[1]);
     let path_ecom = Path::new(path.as_str());
     if !path_ecom.exists() {
         println!("ERROR: path from shop information does not exist");
         return Err(FileError::NotFound);
     }
-    Ok(path)
+
+    let routing = match flag_value(args, ROUTING_FLAG) {
+        Some(name) => match routing_from_name(name) {
+            Some(routing) => routing,
+            None => {
+                println!("ERROR: unknown routing strategy {}", name);
+                return Err(FileError::WrongFormat);
+            }
+        },
+        None => Box::new(NearestRouting),
+    };
+    let recover = args.iter().any(|arg| arg == RECOVER_FLAG);
+    Ok((path, routing, recover))
+}
+
+/// Returns the value following the given flag in the arguments, if any
+fn flag_value<'a>(args: &'a [String], flag: &str) -> Option<&'a str> {
+    args.iter()
+        .position(|arg| arg == flag)
+        .and_then(|position| args.get(position + 1))
+        .map(|value| value.as_str())
 }
 
 /// Waits for the user to press enter to start the program
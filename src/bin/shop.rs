@@ -1,8 +1,12 @@
 use actix_rt::System;
+use concurrentes::config::Config;
 use concurrentes::error::FileError;
+use concurrentes::shop::inventory_log::InventoryLog;
 use concurrentes::shop::process_local_orders::ProcessLocalOrders;
 use concurrentes::shop::shop_actor::Shop;
-use concurrentes::shop::shop_server_side::initiate_shop_server_side;
+use concurrentes::shop::shop_server_side::{initiate_shop_server_side, ShopRecipients};
+use concurrentes::shop::supplier::Supplier;
+use concurrentes::zones::ZoneMap;
 use std::env;
 use std::io::{stdin, stdout, Write};
 extern crate actix;
@@ -15,7 +19,16 @@ fn main() {
     let system = System::new();
 
     system.block_on(async {
-        let (path_shop, path_orders) = match get_args() {
+        let args: Vec<String> = env::args().collect();
+        let config = match Config::from_args(&args) {
+            Ok(config) => config,
+            Err(error) => {
+                println!("ERROR leyendo configuracion: {:?}", error);
+                System::current().stop();
+                return;
+            }
+        };
+        let (path_shop, path_orders) = match get_args(&args, &config) {
             Ok(args) => args,
             Err(_) => {
                 System::current().stop();
@@ -23,7 +36,7 @@ fn main() {
             }
         };
 
-        let shop = match Shop::from_file(path_shop.as_str()) {
+        let mut shop = match Shop::from_file(path_shop.as_str()) {
             Ok(shop) => shop,
             Err(error) => {
                 println!("ERROR creando Shop: {:?}", error);
@@ -32,6 +45,40 @@ fn main() {
             }
         };
 
+        // el stock se restaura del inventario guardado, si la tienda ya tiene uno
+        match InventoryLog::restore(&config.inventories_path, &shop.name) {
+            Ok(Some(stock)) => {
+                println!("Stock restaurado:");
+                for product in &stock {
+                    println!("  - {:<3} x {}", product.stock, product.id);
+                }
+                println!("===\n");
+                shop.stock = stock;
+            }
+            Ok(None) => {}
+            Err(error) => {
+                println!("ERROR abriendo inventario: {:?}", error);
+                System::current().stop();
+                return;
+            }
+        };
+        shop.inventory_path = Some(config.inventories_path.clone());
+
+        shop.zones = match ZoneMap::from_optional_file(&config.zones_path) {
+            Ok(zones) => zones,
+            Err(error) => {
+                println!("ERROR leyendo zonas: {:?}", error);
+                System::current().stop();
+                return;
+            }
+        };
+        // el ecom revisa que el mapa llegue a las zonas de sus pedidos
+        if let Err(error) = shop.zones.check_coverage(&[shop.location as i32], &[]) {
+            println!("ERROR en el mapa de zonas: {:?}", error);
+            System::current().stop();
+            return;
+        }
+
         let orders = match Shop::orders_from_file(path_orders.as_str()) {
             Ok(orders) => orders,
             Err(error) => {
@@ -41,9 +88,26 @@ fn main() {
             }
         };
 
+        // la tienda solo recibe reposiciones si tiene un archivo de reposiciones
+        let path_restocks = format!("{}/{}_reposiciones.txt", config.orders_path, &args[1]);
+        let restocks = match Supplier::restocks_from_file(&path_restocks) {
+            Ok(restocks) => restocks,
+            Err(FileError::NotFound) => Vec::new(),
+            Err(error) => {
+                println!("ERROR leyendo reposiciones: {:?}", error);
+                System::current().stop();
+                return;
+            }
+        };
+
+        shop.restocks = restocks;
+        shop.rng = config.rng();
+        shop.config = config;
+
         enter_to_start();
 
         let address = shop.address.clone();
+        let identity = shop.identity();
         let shop = shop.start();
 
         if let Err(err) = shop.send(ProcessLocalOrders(orders)).await {
@@ -51,7 +115,9 @@ fn main() {
             System::current().stop();
             return;
         };
-        if let Err(err) = initiate_shop_server_side(shop.recipient(), address).await {
+        if let Err(err) =
+            initiate_shop_server_side(ShopRecipients::new(&shop), identity, address).await
+        {
             println!("ERROR: {:?}", err);
             System::current().stop()
         };
@@ -61,20 +127,19 @@ fn main() {
     }
 }
 
-/// Gets the path from the shop files from the command line arguments
-fn get_args() -> Result<(String, String), FileError> {
-    let args: Vec<String> = env::args().collect();
-
+/// Gets the path from the shop files from the command line arguments, in the directories
+/// of the configuration
+fn get_args(args: &[String], config: &Config) -> Result<(String, String), FileError> {
     if args.len() < CANT_ARGS {
         println!("ERROR: shop files not provided");
         return Err(FileError::NotFound);
     }
-    let path_shop = format!("tiendas/{}.txt", &args[1]);
+    let path_shop = format!("{}/{}.txt", config.shops_path, &args[1]);
     if !Path::new(&path_shop.as_str()).exists() {
         println!("ERROR: path from shop information does not exist");
         return Err(FileError::NotFound);
     }
-    let path_orders = format!("pedidos/{}.txt", &args[1]);
+    let path_orders = format!("{}/{}.txt", config.orders_path, &args[1]);
     if !Path::new(&path_orders.as_str()).exists() {
         println!("ERROR: path from orders information does not exist");
         return Err(FileError::NotFound);
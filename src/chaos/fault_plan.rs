@@ -0,0 +1,189 @@
+use std::{ops::RangeInclusive, time::Duration};
+
+use rand::Rng;
+
+use crate::error::ProxyError;
+
+/// What the proxy does with a frame
+#[derive(Debug, Clone, PartialEq)]
+pub enum Fault {
+    /// The frame goes through untouched
+    Pass,
+    /// The frame is thrown away
+    Drop,
+    /// The frame is sent twice
+    Duplicate,
+    /// The frame is held for the given time, along with the frames after it
+    Delay(Duration),
+    /// The frame is held for the given time, while the frames after it go through
+    Reorder(Duration),
+    /// Only the first half of the frame is sent, and then the connection is closed
+    Truncate,
+}
+
+/// Faults applied to the frames going in one direction through the proxy. Each rate is the
+/// probability of a frame getting that fault, and at most one fault is applied to each frame
+#[derive(Debug, Clone, PartialEq)]
+pub struct FrameFaults {
+    pub drop_rate: f64,
+    pub duplicate_rate: f64,
+    pub delay_rate: f64,
+    pub reorder_rate: f64,
+    pub truncate_rate: f64,
+    /// Time a delayed or reordered frame is held
+    pub delay_millis: RangeInclusive<u64>,
+}
+
+impl Default for FrameFaults {
+    fn default() -> Self {
+        Self {
+            drop_rate: 0.0,
+            duplicate_rate: 0.0,
+            delay_rate: 0.0,
+            reorder_rate: 0.0,
+            truncate_rate: 0.0,
+            delay_millis: 0..=0,
+        }
+    }
+}
+
+impl FrameFaults {
+    /// Draws the fault for the next frame
+    pub fn choose<R: Rng>(&self, rng: &mut R) -> Fault {
+        let draw: f64 = rng.gen();
+        let mut limit = 0.0;
+        let faults = [
+            (self.drop_rate, Fault::Drop),
+            (self.duplicate_rate, Fault::Duplicate),
+            (self.delay_rate, Fault::Delay(Duration::ZERO)),
+            (self.reorder_rate, Fault::Reorder(Duration::ZERO)),
+            (self.truncate_rate, Fault::Truncate),
+        ];
+        for (rate, fault) in faults {
+            limit += rate;
+            if draw < limit {
+                return match fault {
+                    Fault::Delay(_) => Fault::Delay(self.delay(rng)),
+                    Fault::Reorder(_) => Fault::Reorder(self.delay(rng)),
+                    fault => fault,
+                };
+            }
+        }
+        Fault::Pass
+    }
+
+    fn delay<R: Rng>(&self, rng: &mut R) -> Duration {
+        Duration::from_millis(rng.gen_range(self.delay_millis.clone()))
+    }
+
+    /// Checks that every rate is a probability, they add up to at most 1 and the delay range
+    /// is not empty
+    pub fn validate(&self) -> Result<(), ProxyError> {
+        let rates = [
+            ("drop_rate", self.drop_rate),
+            ("duplicate_rate", self.duplicate_rate),
+            ("delay_rate", self.delay_rate),
+            ("reorder_rate", self.reorder_rate),
+            ("truncate_rate", self.truncate_rate),
+        ];
+        for (key, rate) in rates {
+            if !(0.0..=1.0).contains(&rate) {
+                return Err(ProxyError::InvalidPlan(key.to_string()));
+            }
+        }
+        if rates.iter().map(|(_, rate)| rate).sum::<f64>() > 1.0 {
+            return Err(ProxyError::InvalidPlan("rates".to_string()));
+        }
+        if self.delay_millis.is_empty() {
+            return Err(ProxyError::InvalidPlan("delay_millis".to_string()));
+        }
+        Ok(())
+    }
+}
+
+/// Time while the link is cut: open connections are closed when it starts, and new ones are
+/// refused until it ends
+#[derive(Debug, Clone, PartialEq)]
+pub struct Partition {
+    /// Time since the proxy started
+    pub start: Duration,
+    pub duration: Duration,
+}
+
+/// Faults the proxy applies to the link between an ecom and a shop
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct FaultPlan {
+    /// Faults of the frames sent by the ecom
+    pub to_shop: FrameFaults,
+    /// Faults of the frames sent by the shop
+    pub to_ecom: FrameFaults,
+    pub partitions: Vec<Partition>,
+    /// Seed of the random numbers used to choose the faults
+    pub seed: u64,
+}
+
+impl FaultPlan {
+    /// Plan with the same faults in both directions
+    pub fn symmetric(faults: FrameFaults, seed: u64) -> Self {
+        Self {
+            to_shop: faults.clone(),
+            to_ecom: faults,
+            partitions: Vec::new(),
+            seed,
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), ProxyError> {
+        self.to_shop.validate()?;
+        self.to_ecom.validate()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+
+    #[test]
+    fn test_faults_follow_their_rates() {
+        let faults = FrameFaults {
+            drop_rate: 0.25,
+            duplicate_rate: 0.25,
+            reorder_rate: 0.5,
+            delay_millis: 10..=20,
+            ..FrameFaults::default()
+        };
+        let mut rng = StdRng::seed_from_u64(0);
+
+        let chosen: Vec<Fault> = (0..1000).map(|_| faults.choose(&mut rng)).collect();
+
+        let dropped = chosen.iter().filter(|f| **f == Fault::Drop).count();
+        let reordered = chosen
+            .iter()
+            .filter(|f| matches!(f, Fault::Reorder(d) if (10..=20).contains(&d.as_millis())))
+            .count();
+        assert!((200..300).contains(&dropped));
+        assert!((450..550).contains(&reordered));
+        assert!(!chosen.contains(&Fault::Pass));
+        assert!(!chosen.contains(&Fault::Truncate));
+        assert_eq!(FrameFaults::default().choose(&mut rng), Fault::Pass);
+    }
+
+    #[test]
+    fn test_invalid_plans() {
+        let too_likely = FrameFaults {
+            drop_rate: 0.6,
+            duplicate_rate: 0.6,
+            ..FrameFaults::default()
+        };
+        let negative = FrameFaults {
+            delay_rate: -0.1,
+            ..FrameFaults::default()
+        };
+
+        assert!(FaultPlan::default().validate().is_ok());
+        assert!(FaultPlan::symmetric(too_likely, 0).validate().is_err());
+        assert!(FaultPlan::symmetric(negative, 0).validate().is_err());
+    }
+}
@@ -0,0 +1,289 @@
+use std::sync::{Arc, Mutex};
+
+use colored::Colorize;
+use futures::future::{join, select};
+use rand::{rngs::StdRng, SeedableRng};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{
+        tcp::{OwnedReadHalf, OwnedWriteHalf},
+        TcpListener, TcpStream,
+    },
+    sync::{mpsc, watch},
+};
+
+use crate::{
+    clock,
+    error::ProxyError,
+    protocol::codec::{LENGTH_BYTES, MAX_FRAME_BYTES},
+};
+
+use super::fault_plan::{Fault, FaultPlan, FrameFaults};
+
+/// Bytes written by the proxy to one side of a connection
+struct Chunk {
+    bytes: Vec<u8>,
+    /// Whether the connection has to be closed after writing them
+    last: bool,
+}
+
+impl Chunk {
+    fn frame(bytes: Vec<u8>) -> Self {
+        Self { bytes, last: false }
+    }
+}
+
+/// TCP proxy placed between an ecom and a shop, that applies the faults of a plan to the
+/// frames going through it. The ecom connects to the address of the proxy, and the proxy
+/// opens a connection to the shop for each connection it accepts.
+pub struct FaultProxy {
+    /// Address the ecom has to connect to, instead of the one of the shop
+    pub address: String,
+    partitioned: Arc<watch::Sender<bool>>,
+}
+
+impl FaultProxy {
+    /// Starts a proxy listening in the given address, which forwards the connections to the
+    /// shop in the upstream address. The partitions of the plan are scheduled from now on.
+    pub async fn start(address: &str, upstream: &str, plan: FaultPlan) -> Result<Self, ProxyError> {
+        plan.validate()?;
+        let listener = TcpListener::bind(address)
+            .await
+            .map_err(|_| ProxyError::CannotListen)?;
+        let address = listener
+            .local_addr()
+            .map_err(|_| ProxyError::CannotListen)?
+            .to_string();
+        let (partitioned, cut) = watch::channel(false);
+
+        let rng = Arc::new(Mutex::new(StdRng::seed_from_u64(plan.seed)));
+        let upstream = upstream.to_string();
+        let (to_shop, to_ecom) = (plan.to_shop.clone(), plan.to_ecom.clone());
+        actix::spawn(async move {
+            while let Ok((ecom, _)) = listener.accept().await {
+                if *cut.borrow() {
+                    continue; // el enlace esta cortado, la conexion se cierra al soltarla
+                }
+                let shop = match TcpStream::connect(&upstream).await {
+                    Ok(shop) => shop,
+                    Err(_) => continue,
+                };
+                actix::spawn(relay(
+                    ecom,
+                    shop,
+                    (to_shop.clone(), to_ecom.clone()),
+                    rng.clone(),
+                    cut.clone(),
+                ));
+            }
+        });
+
+        let proxy = Self {
+            address,
+            partitioned: Arc::new(partitioned),
+        };
+        for partition in plan.partitions {
+            let partitioned = proxy.partitioned.clone();
+            actix::spawn(async move {
+                clock::sleep(partition.start).await;
+                set_partitioned(&partitioned, true);
+                clock::sleep(partition.duration).await;
+                set_partitioned(&partitioned, false);
+            });
+        }
+        Ok(proxy)
+    }
+
+    /// Cuts the link: closes the open connections and refuses new ones until healed
+    pub fn partition(&self) {
+        set_partitioned(&self.partitioned, true);
+    }
+
+    /// Lets new connections through again after a partition
+    pub fn heal(&self) {
+        set_partitioned(&self.partitioned, false);
+    }
+}
+
+fn set_partitioned(partitioned: &watch::Sender<bool>, value: bool) {
+    if *partitioned.borrow() != value {
+        let state = if value { "cortado" } else { "restablecido" };
+        println!("{} Enlace {}", "[PROXY]".yellow(), state);
+    }
+    partitioned.send_replace(value);
+}
+
+/// Forwards the frames between the ecom and the shop, until either side closes its
+/// connection or the link is cut. Then both connections are closed.
+async fn relay(
+    ecom: TcpStream,
+    shop: TcpStream,
+    (to_shop, to_ecom): (FrameFaults, FrameFaults),
+    rng: Arc<Mutex<StdRng>>,
+    mut cut: watch::Receiver<bool>,
+) {
+    let (ecom_read, ecom_write) = ecom.into_split();
+    let (shop_read, shop_write) = shop.into_split();
+
+    let forwarding = select(
+        Box::pin(forward(
+            ecom_read,
+            shop_write,
+            to_shop,
+            rng.clone(),
+            "tienda",
+        )),
+        Box::pin(forward(shop_read, ecom_write, to_ecom, rng, "ecom")),
+    );
+    let partition = Box::pin(async move {
+        if cut.wait_for(|cut| *cut).await.is_err() {
+            // sin el proxy no hay quien corte el enlace
+            futures::future::pending::<()>().await;
+        }
+    });
+    select(forwarding, partition).await;
+}
+
+/// Reads the frames from one side and writes them to the other after applying the faults.
+/// Delayed frames hold back the ones after them, while reordered frames are written later
+/// without holding anything back.
+async fn forward(
+    mut read: OwnedReadHalf,
+    mut write: OwnedWriteHalf,
+    faults: FrameFaults,
+    rng: Arc<Mutex<StdRng>>,
+    destination: &'static str,
+) {
+    let (chunks, mut pending) = mpsc::unbounded_channel::<Chunk>();
+
+    let reader = async move {
+        while let Some(frame) = read_frame(&mut read).await {
+            let fault = match rng.lock() {
+                Ok(mut rng) => faults.choose(&mut *rng),
+                Err(_) => Fault::Pass,
+            };
+            if fault != Fault::Pass {
+                println!(
+                    "{} Frame hacia {}: {:?}",
+                    "[PROXY]".yellow(),
+                    destination,
+                    fault
+                );
+            }
+
+            let sent = match fault {
+                Fault::Pass => chunks.send(Chunk::frame(frame)).is_ok(),
+                Fault::Drop => true,
+                Fault::Duplicate => {
+                    chunks.send(Chunk::frame(frame.clone())).is_ok()
+                        && chunks.send(Chunk::frame(frame)).is_ok()
+                }
+                Fault::Delay(delay) => {
+                    clock::sleep(delay).await;
+                    chunks.send(Chunk::frame(frame)).is_ok()
+                }
+                Fault::Reorder(delay) => {
+                    let chunks = chunks.clone();
+                    actix::spawn(async move {
+                        clock::sleep(delay).await;
+                        let _ = chunks.send(Chunk::frame(frame));
+                    });
+                    true
+                }
+                Fault::Truncate => {
+                    let half = frame.len() / 2;
+                    let _ = chunks.send(Chunk {
+                        bytes: frame[..half].to_vec(),
+                        last: true,
+                    });
+                    false
+                }
+            };
+            if !sent {
+                break;
+            }
+        }
+    };
+
+    let writer = async move {
+        while let Some(chunk) = pending.recv().await {
+            if write.write_all(&chunk.bytes).await.is_err() || chunk.last {
+                break;
+            }
+        }
+    };
+
+    join(reader, writer).await;
+}
+
+/// Reads the next whole frame, with its length prefix. Returns None once the connection is
+/// closed or the frame is too large to be valid
+async fn read_frame(read: &mut OwnedReadHalf) -> Option<Vec<u8>> {
+    let mut length = [0u8; LENGTH_BYTES];
+    read.read_exact(&mut length).await.ok()?;
+    let size = u32::from_be_bytes(length) as usize;
+    if size > MAX_FRAME_BYTES {
+        return None;
+    }
+
+    let mut frame = vec![0u8; LENGTH_BYTES + size];
+    frame[..LENGTH_BYTES].copy_from_slice(&length);
+    read.read_exact(&mut frame[LENGTH_BYTES..]).await.ok()?;
+    Some(frame)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use actix_codec::Decoder;
+    use bytes::BytesMut;
+    use tokio::{io::split, sync::Mutex};
+
+    use super::*;
+    use crate::protocol::{
+        codec::{send_message, WireCodec},
+        wire_message::WireMessage,
+    };
+
+    #[actix_rt::test]
+    async fn test_proxy_duplicates_frames_and_cuts_the_link() {
+        let shop = TcpListener::bind("localhost:0").await.unwrap();
+        let upstream = shop.local_addr().unwrap().to_string();
+        let plan = FaultPlan {
+            to_shop: FrameFaults {
+                duplicate_rate: 1.0,
+                ..FrameFaults::default()
+            },
+            ..FaultPlan::default()
+        };
+        let proxy = FaultProxy::start("localhost:0", &upstream, plan)
+            .await
+            .unwrap();
+
+        let ecom = TcpStream::connect(&proxy.address).await.unwrap();
+        let (mut ecom_read, ecom_write) = split(ecom);
+        send_message(&Arc::new(Mutex::new(ecom_write)), WireMessage::Ping)
+            .await
+            .unwrap();
+
+        // la tienda recibe el Ping dos veces
+        let (mut shop_stream, _) = shop.accept().await.unwrap();
+        let mut codec = WireCodec::default();
+        let mut buffer = BytesMut::new();
+        let mut received = Vec::new();
+        while received.len() < 2 {
+            shop_stream.read_buf(&mut buffer).await.unwrap();
+            while let Some(message) = codec.decode(&mut buffer).unwrap() {
+                received.push(message);
+            }
+        }
+        assert_eq!(received, vec![WireMessage::Ping, WireMessage::Ping]);
+
+        // al cortar el enlace se cierran ambas conexiones
+        proxy.partition();
+        let mut bytes = [0u8; 16];
+        assert_eq!(ecom_read.read(&mut bytes).await.unwrap(), 0);
+        assert_eq!(shop_stream.read(&mut bytes).await.unwrap(), 0);
+    }
+}
@@ -0,0 +1,2 @@
+pub mod fault_plan;
+pub mod fault_proxy;
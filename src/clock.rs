@@ -0,0 +1,21 @@
+//! Clock used by the shops and the ecoms for every delay, timeout and timestamp.
+//!
+//! It runs on the timer of the actix runtime, which `ctx.run_later`, `ctx.notify_later` and
+//! `ctx.run_interval` also use. A test can stop it with `tokio::time::pause` and then move it
+//! forward by hand with `tokio::time::advance`. While stopped, it also jumps to the next
+//! timer whenever the runtime has nothing else to do, so a timeout of seconds passes in no
+//! time at all.
+
+use std::time::Duration;
+
+pub use actix::clock::Instant;
+
+/// Current time of the clock
+pub fn now() -> Instant {
+    Instant::now()
+}
+
+/// Waits until the given time has passed in the clock
+pub async fn sleep(duration: Duration) {
+    actix::clock::sleep(duration).await
+}
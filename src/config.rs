@@ -0,0 +1,463 @@
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, Read},
+    ops::RangeInclusive,
+    path::Path,
+};
+
+use rand::{rngs::StdRng, SeedableRng};
+
+use crate::{
+    constants::{
+        CIRCUIT_BREAKER_COOLDOWN_MILLIS, CIRCUIT_BREAKER_FAILURE_RATE, CIRCUIT_BREAKER_MIN_ORDERS,
+        CIRCUIT_BREAKER_WINDOW, CONFIG_PATH, DELIVER_MILLIS, DELIVER_MILLIS_PER_DISTANCE,
+        DELIVER_RATE, ECOM_MAX_WAITING_MILLIS, ECOM_PROCESING_MILLIS, HEARTBEAT_DOWN_MILLIS,
+        HEARTBEAT_INTERVAL_MILLIS, HEARTBEAT_SUSPECT_MILLIS, INVENTORIES_PATH,
+        INVENTORY_SNAPSHOT_MILLIS, JOURNALS_PATH, LOCAL_PROCESING_MILLIS, ORDERS_PATH,
+        ORDER_TABLE_EXPIRATION_MILLIS, PREPARE_TIMEOUT_MILLIS, PURCHASE_MILLIS,
+        RECONNECT_BASE_MILLIS, RECONNECT_MAX_MILLIS, SHOPS_PATH, STOCK_SHARDS, SUPPLIER_MILLIS,
+        ZONES_PATH,
+    },
+    error::ConfigError,
+};
+
+const CONFIG_FLAG: &str = "--config";
+
+const KEYS: [&str; 27] = [
+    "purchase_millis",
+    "prepare_timeout_millis",
+    "order_table_expiration_millis",
+    "stock_shards",
+    "inventory_snapshot_millis",
+    "local_procesing_millis",
+    "deliver_millis",
+    "deliver_millis_per_distance",
+    "supplier_millis",
+    "ecom_procesing_millis",
+    "ecom_max_waiting_millis",
+    "heartbeat_interval_millis",
+    "heartbeat_suspect_millis",
+    "heartbeat_down_millis",
+    "reconnect_base_millis",
+    "reconnect_max_millis",
+    "circuit_breaker_window",
+    "circuit_breaker_min_orders",
+    "circuit_breaker_failure_rate",
+    "circuit_breaker_cooldown_millis",
+    "deliver_rate",
+    "shops_path",
+    "orders_path",
+    "zones_path",
+    "inventories_path",
+    "journals_path",
+    "seed",
+];
+
+/// Timings, rates and paths used by the shops and the ecoms. The defaults are the ones in
+/// the constants, replaced by the ones in the configuration file and then by the ones given
+/// in the command line.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config {
+    pub purchase_millis: u64,
+    /// Time a shop holds a reservation waiting for the ecom to commit or abort it
+    pub prepare_timeout_millis: u64,
+    /// Time a shop remembers an online order without updates, to answer it if sent again
+    pub order_table_expiration_millis: u64,
+    /// Number of shards the stock of a shop is split in
+    pub stock_shards: usize,
+    /// Time between the snapshots of the stock of each shard
+    pub inventory_snapshot_millis: u64,
+    pub local_procesing_millis: RangeInclusive<u64>,
+    pub deliver_millis: RangeInclusive<u64>,
+    pub deliver_millis_per_distance: u64,
+    /// Time between the restocks of the supplier of a shop
+    pub supplier_millis: RangeInclusive<u64>,
+    pub ecom_procesing_millis: RangeInclusive<u64>,
+    pub ecom_max_waiting_millis: u64,
+    /// Time between the pings the ecom sends to every shop
+    pub heartbeat_interval_millis: u64,
+    /// Time without hearing from a shop before the ecom marks it as suspect
+    pub heartbeat_suspect_millis: u64,
+    /// Time without hearing from a shop before the ecom marks it as down
+    pub heartbeat_down_millis: u64,
+    /// Time before the first attempt to reconnect a shop, doubled with each failed attempt
+    pub reconnect_base_millis: u64,
+    /// Longest time between the attempts to reconnect a shop
+    pub reconnect_max_millis: u64,
+    /// Number of last orders of a shop its circuit breaker looks at
+    pub circuit_breaker_window: usize,
+    /// Orders in the window needed before the circuit breaker of a shop can open
+    pub circuit_breaker_min_orders: usize,
+    /// Rate of failed orders in the window that opens the circuit breaker of a shop
+    pub circuit_breaker_failure_rate: f64,
+    /// Time the circuit breaker of a shop stays open before probing it
+    pub circuit_breaker_cooldown_millis: u64,
+    /// Probability of a delivery reaching the customer
+    pub deliver_rate: f64,
+    pub shops_path: String,
+    pub orders_path: String,
+    pub zones_path: String,
+    pub inventories_path: String,
+    pub journals_path: String,
+    /// Seed of the random numbers, so the same seed and inputs give the same outcomes.
+    /// Without it, each run is different
+    pub seed: Option<u64>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            purchase_millis: PURCHASE_MILLIS,
+            prepare_timeout_millis: PREPARE_TIMEOUT_MILLIS,
+            order_table_expiration_millis: ORDER_TABLE_EXPIRATION_MILLIS,
+            stock_shards: STOCK_SHARDS,
+            inventory_snapshot_millis: INVENTORY_SNAPSHOT_MILLIS,
+            local_procesing_millis: LOCAL_PROCESING_MILLIS,
+            deliver_millis: DELIVER_MILLIS,
+            deliver_millis_per_distance: DELIVER_MILLIS_PER_DISTANCE,
+            supplier_millis: SUPPLIER_MILLIS,
+            ecom_procesing_millis: ECOM_PROCESING_MILLIS,
+            ecom_max_waiting_millis: ECOM_MAX_WAITING_MILLIS,
+            heartbeat_interval_millis: HEARTBEAT_INTERVAL_MILLIS,
+            heartbeat_suspect_millis: HEARTBEAT_SUSPECT_MILLIS,
+            heartbeat_down_millis: HEARTBEAT_DOWN_MILLIS,
+            reconnect_base_millis: RECONNECT_BASE_MILLIS,
+            reconnect_max_millis: RECONNECT_MAX_MILLIS,
+            circuit_breaker_window: CIRCUIT_BREAKER_WINDOW,
+            circuit_breaker_min_orders: CIRCUIT_BREAKER_MIN_ORDERS,
+            circuit_breaker_failure_rate: CIRCUIT_BREAKER_FAILURE_RATE,
+            circuit_breaker_cooldown_millis: CIRCUIT_BREAKER_COOLDOWN_MILLIS,
+            deliver_rate: DELIVER_RATE,
+            shops_path: SHOPS_PATH.to_string(),
+            orders_path: ORDERS_PATH.to_string(),
+            zones_path: ZONES_PATH.to_string(),
+            inventories_path: INVENTORIES_PATH.to_string(),
+            journals_path: JOURNALS_PATH.to_string(),
+            seed: None,
+        }
+    }
+}
+
+impl Config {
+    /// Builds the configuration from the command line arguments. The file is the one after
+    /// `--config`, or CONFIG_PATH if it exists. Then every `--<key> <value>` pair replaces
+    /// the value of the key, with dashes instead of underscores (`--purchase-millis 100`).
+    /// Flags that are not keys are left to the binaries.
+    pub fn from_args(args: &[String]) -> Result<Self, ConfigError> {
+        let mut config = match args.iter().position(|arg| arg == CONFIG_FLAG) {
+            Some(position) => {
+                let path = args
+                    .get(position + 1)
+                    .ok_or(ConfigError::MissingValue(CONFIG_FLAG.to_string()))?;
+                Self::from_file(path)?
+            }
+            None if Path::new(CONFIG_PATH).exists() => Self::from_file(CONFIG_PATH)?,
+            None => Self::default(),
+        };
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let key = match arg.strip_prefix("--") {
+                Some(flag) => flag.replace('-', "_"),
+                None => continue,
+            };
+            if !KEYS.contains(&key.as_str()) {
+                continue;
+            }
+            let value = args
+                .next()
+                .ok_or_else(|| ConfigError::MissingValue(arg.clone()))?;
+            config.set(&key, value)?;
+        }
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Reads the configuration from the file in the given path
+    pub fn from_file(path: &str) -> Result<Self, ConfigError> {
+        let file = File::open(path).map_err(|_| ConfigError::NotFound)?;
+        Self::from_reader(file)
+    }
+
+    /// Reads the configuration from the given reader, with one `<key> = <value>` per line.
+    /// Empty lines, `#` comments and `[section]` headers are skipped: sections only group
+    /// the keys. Keys not given keep their default value.
+    pub fn from_reader<T: Read>(content: T) -> Result<Self, ConfigError> {
+        let reader = BufReader::new(content);
+        let mut config = Self::default();
+
+        for (number, line) in reader.lines().enumerate() {
+            let current_line = line.map_err(|_| ConfigError::WrongFormat(number + 1))?;
+            let current_line = current_line.trim();
+            if current_line.is_empty()
+                || current_line.starts_with('#')
+                || current_line.starts_with('[')
+            {
+                continue;
+            }
+
+            match current_line.split_once('=') {
+                Some((key, value)) => config.set(key.trim(), value.trim())?,
+                None => return Err(ConfigError::WrongFormat(number + 1)),
+            }
+        }
+        Ok(config)
+    }
+
+    /// Replaces the value of the given key with the given one
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), ConfigError> {
+        let wrong_value = || ConfigError::WrongValue(key.to_string());
+        match key {
+            "purchase_millis" => self.purchase_millis = value.parse().map_err(|_| wrong_value())?,
+            "prepare_timeout_millis" => {
+                self.prepare_timeout_millis = value.parse().map_err(|_| wrong_value())?
+            }
+            "order_table_expiration_millis" => {
+                self.order_table_expiration_millis = value.parse().map_err(|_| wrong_value())?
+            }
+            "stock_shards" => self.stock_shards = value.parse().map_err(|_| wrong_value())?,
+            "inventory_snapshot_millis" => {
+                self.inventory_snapshot_millis = value.parse().map_err(|_| wrong_value())?
+            }
+            "local_procesing_millis" => {
+                self.local_procesing_millis = parse_range(value).ok_or_else(wrong_value)?
+            }
+            "deliver_millis" => self.deliver_millis = parse_range(value).ok_or_else(wrong_value)?,
+            "deliver_millis_per_distance" => {
+                self.deliver_millis_per_distance = value.parse().map_err(|_| wrong_value())?
+            }
+            "supplier_millis" => {
+                self.supplier_millis = parse_range(value).ok_or_else(wrong_value)?
+            }
+            "ecom_procesing_millis" => {
+                self.ecom_procesing_millis = parse_range(value).ok_or_else(wrong_value)?
+            }
+            "ecom_max_waiting_millis" => {
+                self.ecom_max_waiting_millis = value.parse().map_err(|_| wrong_value())?
+            }
+            "heartbeat_interval_millis" => {
+                self.heartbeat_interval_millis = value.parse().map_err(|_| wrong_value())?
+            }
+            "heartbeat_suspect_millis" => {
+                self.heartbeat_suspect_millis = value.parse().map_err(|_| wrong_value())?
+            }
+            "heartbeat_down_millis" => {
+                self.heartbeat_down_millis = value.parse().map_err(|_| wrong_value())?
+            }
+            "reconnect_base_millis" => {
+                self.reconnect_base_millis = value.parse().map_err(|_| wrong_value())?
+            }
+            "reconnect_max_millis" => {
+                self.reconnect_max_millis = value.parse().map_err(|_| wrong_value())?
+            }
+            "circuit_breaker_window" => {
+                self.circuit_breaker_window = value.parse().map_err(|_| wrong_value())?
+            }
+            "circuit_breaker_min_orders" => {
+                self.circuit_breaker_min_orders = value.parse().map_err(|_| wrong_value())?
+            }
+            "circuit_breaker_failure_rate" => {
+                self.circuit_breaker_failure_rate = value.parse().map_err(|_| wrong_value())?
+            }
+            "circuit_breaker_cooldown_millis" => {
+                self.circuit_breaker_cooldown_millis = value.parse().map_err(|_| wrong_value())?
+            }
+            "deliver_rate" => self.deliver_rate = value.parse().map_err(|_| wrong_value())?,
+            "shops_path" => self.shops_path = value.to_string(),
+            "orders_path" => self.orders_path = value.to_string(),
+            "zones_path" => self.zones_path = value.to_string(),
+            "inventories_path" => self.inventories_path = value.to_string(),
+            "journals_path" => self.journals_path = value.to_string(),
+            "seed" => self.seed = Some(value.parse().map_err(|_| wrong_value())?),
+            _ => return Err(ConfigError::UnknownKey(key.to_string())),
+        }
+        Ok(())
+    }
+
+    /// Random number generator for an actor: seeded if a seed was given, otherwise seeded
+    /// from the operating system
+    pub fn rng(&self) -> StdRng {
+        match self.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        }
+    }
+
+    /// Random number generator for an actor that runs alongside another one with the same
+    /// configuration: the seed, if given, is mixed with the salt so both actors do not draw
+    /// the same numbers
+    pub fn salted_rng(&self, salt: u64) -> StdRng {
+        match self.seed {
+            Some(seed) => StdRng::seed_from_u64(seed ^ salt),
+            None => StdRng::from_entropy(),
+        }
+    }
+
+    /// Checks that the values can be used: ranges are not empty, the rates are probabilities,
+    /// the ecom and the shops wait for each other some time, the ecom pings the shops and
+    /// suspects them before marking them as down, retries reconnecting them with a growing
+    /// delay, its circuit breakers need some orders in their window, the shops have some
+    /// shards, take snapshots and remember the orders some time, and the paths are given
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let ranges = [
+            ("local_procesing_millis", &self.local_procesing_millis),
+            ("deliver_millis", &self.deliver_millis),
+            ("supplier_millis", &self.supplier_millis),
+            ("ecom_procesing_millis", &self.ecom_procesing_millis),
+        ];
+        for (key, range) in ranges {
+            if range.is_empty() {
+                return Err(ConfigError::Invalid(key.to_string()));
+            }
+        }
+        if !(0.0..=1.0).contains(&self.deliver_rate) {
+            return Err(ConfigError::Invalid("deliver_rate".to_string()));
+        }
+        if self.ecom_max_waiting_millis == 0 {
+            return Err(ConfigError::Invalid("ecom_max_waiting_millis".to_string()));
+        }
+        if self.heartbeat_interval_millis == 0 {
+            return Err(ConfigError::Invalid(
+                "heartbeat_interval_millis".to_string(),
+            ));
+        }
+        if self.heartbeat_suspect_millis >= self.heartbeat_down_millis {
+            return Err(ConfigError::Invalid("heartbeat_suspect_millis".to_string()));
+        }
+        if self.reconnect_base_millis == 0 || self.reconnect_base_millis > self.reconnect_max_millis
+        {
+            return Err(ConfigError::Invalid("reconnect_base_millis".to_string()));
+        }
+        if !(1..=self.circuit_breaker_window).contains(&self.circuit_breaker_min_orders) {
+            return Err(ConfigError::Invalid(
+                "circuit_breaker_min_orders".to_string(),
+            ));
+        }
+        if !(self.circuit_breaker_failure_rate > 0.0 && self.circuit_breaker_failure_rate <= 1.0) {
+            return Err(ConfigError::Invalid(
+                "circuit_breaker_failure_rate".to_string(),
+            ));
+        }
+        if self.prepare_timeout_millis == 0 {
+            return Err(ConfigError::Invalid("prepare_timeout_millis".to_string()));
+        }
+        if self.stock_shards == 0 {
+            return Err(ConfigError::Invalid("stock_shards".to_string()));
+        }
+        if self.inventory_snapshot_millis == 0 {
+            return Err(ConfigError::Invalid(
+                "inventory_snapshot_millis".to_string(),
+            ));
+        }
+        if self.order_table_expiration_millis == 0 {
+            return Err(ConfigError::Invalid(
+                "order_table_expiration_millis".to_string(),
+            ));
+        }
+
+        let paths = [
+            ("shops_path", &self.shops_path),
+            ("orders_path", &self.orders_path),
+            ("zones_path", &self.zones_path),
+            ("inventories_path", &self.inventories_path),
+            ("journals_path", &self.journals_path),
+        ];
+        for (key, path) in paths {
+            if path.is_empty() {
+                return Err(ConfigError::Invalid(key.to_string()));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Parses a `<min>-<max>` range of milliseconds, or a single value
+fn parse_range(value: &str) -> Option<RangeInclusive<u64>> {
+    match value.split_once('-') {
+        Some((min, max)) => Some(min.trim().parse().ok()?..=max.trim().parse().ok()?),
+        None => {
+            let millis = value.parse().ok()?;
+            Some(millis..=millis)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::Rng;
+
+    use super::*;
+
+    #[test]
+    fn test_config_from_reader() {
+        let content = "# tiempos\n\
+                       [tienda]\n\
+                       purchase_millis = 50\n\
+                       local_procesing_millis = 10-20\n\
+                       \n\
+                       [ecom]\n\
+                       ecom_max_waiting_millis=1000\n\
+                       deliver_rate = 1\n\
+                       orders_path = otros_pedidos\n";
+
+        let config = Config::from_reader(content.as_bytes()).unwrap();
+
+        assert_eq!(config.purchase_millis, 50);
+        assert_eq!(config.local_procesing_millis, 10..=20);
+        assert_eq!(config.ecom_max_waiting_millis, 1000);
+        assert_eq!(config.deliver_rate, 1.0);
+        assert_eq!(config.orders_path, "otros_pedidos");
+        assert_eq!(config.deliver_millis, DELIVER_MILLIS);
+
+        assert!(matches!(
+            Config::from_reader("purchase_millis = rapido".as_bytes()),
+            Err(ConfigError::WrongValue(_))
+        ));
+        assert!(matches!(
+            Config::from_reader("compra = 10".as_bytes()),
+            Err(ConfigError::UnknownKey(_))
+        ));
+    }
+
+    #[test]
+    fn test_config_from_args_overrides_and_validates() {
+        // un archivo propio, asi la prueba no depende del configuracion.ini del repositorio
+        let path = std::env::temp_dir().join(format!("configuracion-{}.ini", std::process::id()));
+        std::fs::write(&path, "purchase_millis = 50\ndeliver_rate = 1\n").unwrap();
+        let path = path.to_string_lossy().to_string();
+        let args = |args: &[&str]| -> Vec<String> {
+            args.iter()
+                .chain(["--config", path.as_str()].iter())
+                .map(|arg| arg.to_string())
+                .collect()
+        };
+
+        let config = Config::from_args(&args(&["shop", "tienda1", "--recover"])).unwrap();
+        assert_eq!(config.purchase_millis, 50);
+        assert_eq!(config.deliver_rate, 1.0);
+        assert_eq!(config.seed, None);
+
+        let config = Config::from_args(&args(&["shop", "tienda1", "--purchase-millis", "10"]));
+        assert_eq!(config.unwrap().purchase_millis, 10);
+
+        let config = Config::from_args(&args(&["ecom", "ecom1", "--seed", "42"])).unwrap();
+        let (mut first, mut second) = (config.rng(), config.rng());
+        let first: Vec<u64> = (0..4).map(|_| first.gen()).collect();
+        let second: Vec<u64> = (0..4).map(|_| second.gen()).collect();
+        assert_eq!(first, second);
+        let mut salted = config.salted_rng(1);
+        let salted: Vec<u64> = (0..4).map(|_| salted.gen()).collect();
+        assert_ne!(first, salted);
+
+        assert!(matches!(
+            Config::from_args(&args(&["ecom", "ecom1", "--deliver-rate", "1.5"])),
+            Err(ConfigError::Invalid(_))
+        ));
+        assert!(matches!(
+            Config::from_args(&args(&["ecom", "ecom1", "--deliver-millis", "700-500"])),
+            Err(ConfigError::Invalid(_))
+        ));
+        let _ = std::fs::remove_file(&path);
+    }
+}
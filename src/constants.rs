@@ -1,13 +1,57 @@
 use std::ops::RangeInclusive;
 
-pub const PURCHASE_MILLIS: u64 = 200; // blocking
+pub const PURCHASE_MILLIS: u64 = 200; // non-blocking
 
 pub const LOCAL_PROCESING_MILLIS: RangeInclusive<u64> = 100..=300; // non-blocking
 
 pub const DELIVER_MILLIS: RangeInclusive<u64> = 500..=700; // non-blocking
 
+pub const DELIVER_MILLIS_PER_DISTANCE: u64 = 100; // non-blocking
+
+pub const SUPPLIER_MILLIS: RangeInclusive<u64> = 2000..=4000; // non-blocking
+
+pub const SUPPLIER_SALT: u64 = 0x5eed_5a17_0000_0001;
+
 pub const ECOM_PROCESING_MILLIS: RangeInclusive<u64> = 250..=400; // non-blocking
 
 pub const ECOM_MAX_WAITING_MILLIS: u64 = 5000; // non-blocking
 
+pub const PREPARE_TIMEOUT_MILLIS: u64 = 10000; // non-blocking
+
+pub const HEARTBEAT_INTERVAL_MILLIS: u64 = 1000; // non-blocking
+
+pub const HEARTBEAT_SUSPECT_MILLIS: u64 = 2500;
+
+pub const HEARTBEAT_DOWN_MILLIS: u64 = 6000;
+
+pub const RECONNECT_BASE_MILLIS: u64 = 500; // non-blocking
+
+pub const RECONNECT_MAX_MILLIS: u64 = 10000; // non-blocking
+
+pub const CIRCUIT_BREAKER_WINDOW: usize = 10;
+
+pub const CIRCUIT_BREAKER_MIN_ORDERS: usize = 4;
+
+pub const CIRCUIT_BREAKER_FAILURE_RATE: f64 = 0.5;
+
+pub const CIRCUIT_BREAKER_COOLDOWN_MILLIS: u64 = 15000;
+
+pub const STOCK_SHARDS: usize = 4;
+
+pub const INVENTORY_SNAPSHOT_MILLIS: u64 = 5000; // non-blocking
+
+pub const ORDER_TABLE_EXPIRATION_MILLIS: u64 = 60000;
+
 pub const DELIVER_RATE: f64 = 0.75;
+
+pub const ZONES_PATH: &str = "zonas/mapa.txt";
+
+pub const SHOPS_PATH: &str = "tiendas";
+
+pub const ORDERS_PATH: &str = "pedidos";
+
+pub const JOURNALS_PATH: &str = "diarios";
+
+pub const INVENTORIES_PATH: &str = "inventarios";
+
+pub const CONFIG_PATH: &str = "configuracion.ini";
@@ -0,0 +1,242 @@
+use std::{collections::VecDeque, time::Duration};
+
+use crate::{
+    clock::{self, Instant},
+    config::Config,
+    states::{BreakerState, OnlinePurchaseState},
+};
+
+/// Circuit breaker of a connected shop, driven by the outcome of the last orders sent to it.
+/// The window, thresholds and cooldown are the ones of the configuration.
+/// - CLOSED: the shop gets orders as usual.
+/// - OPEN: too many of the last orders failed, the shop gets no orders until the cooldown passes.
+/// - HALF_OPEN: the cooldown passed and a single order is sent to probe the shop.
+#[derive(Debug, Clone)]
+pub struct CircuitBreaker {
+    pub state: BreakerState,
+    /// Last outcomes, true if the order succeeded
+    outcomes: VecDeque<bool>,
+    /// Moment the breaker was opened or the probe was sent
+    since: Instant,
+    probing: bool,
+}
+
+impl Default for CircuitBreaker {
+    fn default() -> Self {
+        Self {
+            state: BreakerState::CLOSED,
+            outcomes: VecDeque::new(),
+            since: clock::now(),
+            probing: false,
+        }
+    }
+}
+
+/// Answers of the shops to the latest request of an order, so their circuit breakers record
+/// a single outcome per request: a failure if the first answer is REJECTED or there is no
+/// answer in time, and otherwise the end of the delivery, DELIVERED or LOST
+#[derive(Debug, Clone, Default)]
+pub struct RequestOutcomes {
+    /// Shops that answered their latest request, and whether its outcome was recorded
+    answered: Vec<(i32, bool)>,
+}
+
+impl RequestOutcomes {
+    /// Forgets the answers of the shop, as it gets a new request
+    pub fn requested(&mut self, zone_id: i32) {
+        self.answered.retain(|(zone, _)| *zone != zone_id);
+    }
+
+    /// Registers the state answered by the shop. Returns whether the request succeeded, if
+    /// this answer decides it and its outcome was not recorded yet
+    pub fn answer(&mut self, zone_id: i32, state: &OnlinePurchaseState) -> Option<bool> {
+        let position = self.answered.iter().position(|(zone, _)| *zone == zone_id);
+        let first = position.is_none();
+        if position.is_some_and(|position| self.answered[position].1) {
+            return None; // respuestas repetidas o posteriores al resultado
+        }
+        let outcome = match state {
+            OnlinePurchaseState::REJECTED if first => Some(false),
+            OnlinePurchaseState::LOST => Some(false),
+            OnlinePurchaseState::DELIVERED => Some(true),
+            _ => None,
+        };
+        match position {
+            Some(position) => self.answered[position].1 = outcome.is_some(),
+            None => self.answered.push((zone_id, outcome.is_some())),
+        }
+        outcome
+    }
+
+    /// Registers that the shop did not answer in time. Returns true if this is the outcome
+    /// of the request, that is, if none was recorded yet
+    pub fn timed_out(&mut self, zone_id: i32) -> bool {
+        match self.answered.iter_mut().find(|(zone, _)| *zone == zone_id) {
+            Some((_, true)) => false,
+            Some((_, recorded)) => {
+                *recorded = true;
+                true
+            }
+            None => {
+                self.answered.push((zone_id, true));
+                true
+            }
+        }
+    }
+}
+
+impl CircuitBreaker {
+    fn cooled_down(&self, now: Instant, config: &Config) -> bool {
+        now.saturating_duration_since(self.since)
+            >= Duration::from_millis(config.circuit_breaker_cooldown_millis)
+    }
+
+    /// Returns true if an order can be sent to the shop
+    pub fn allows(&self, now: Instant, config: &Config) -> bool {
+        match self.state {
+            BreakerState::CLOSED => true,
+            BreakerState::OPEN => self.cooled_down(now, config),
+            // si la prueba nunca se resolvio, se permite otra
+            BreakerState::HALF_OPEN => !self.probing || self.cooled_down(now, config),
+        }
+    }
+
+    /// Registers that an order was sent to the shop. Returns the new state if it changed
+    pub fn request(&mut self, now: Instant) -> Option<BreakerState> {
+        match self.state {
+            BreakerState::CLOSED => None,
+            BreakerState::OPEN | BreakerState::HALF_OPEN => {
+                self.probing = true;
+                self.since = now;
+                self.set_state(BreakerState::HALF_OPEN)
+            }
+        }
+    }
+
+    /// Registers the outcome of an order sent to the shop. Returns the new state if it changed
+    pub fn record(&mut self, success: bool, now: Instant, config: &Config) -> Option<BreakerState> {
+        match self.state {
+            BreakerState::HALF_OPEN if success => {
+                self.outcomes.clear();
+                self.probing = false;
+                self.set_state(BreakerState::CLOSED)
+            }
+            BreakerState::HALF_OPEN => self.open(now),
+            BreakerState::OPEN => None, // respuestas tardias de pedidos anteriores
+            BreakerState::CLOSED => {
+                self.outcomes.push_back(success);
+                if self.outcomes.len() > config.circuit_breaker_window {
+                    self.outcomes.pop_front();
+                }
+                if self.outcomes.len() >= config.circuit_breaker_min_orders
+                    && self.failure_rate() >= config.circuit_breaker_failure_rate
+                {
+                    return self.open(now);
+                }
+                None
+            }
+        }
+    }
+
+    fn failure_rate(&self) -> f64 {
+        let failures = self.outcomes.iter().filter(|success| !**success).count();
+        failures as f64 / self.outcomes.len() as f64
+    }
+
+    fn open(&mut self, now: Instant) -> Option<BreakerState> {
+        self.since = now;
+        self.probing = false;
+        self.set_state(BreakerState::OPEN)
+    }
+
+    fn set_state(&mut self, state: BreakerState) -> Option<BreakerState> {
+        if self.state == state {
+            return None;
+        }
+        self.state = state.clone();
+        Some(state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_breaker_opens_probes_and_closes() {
+        let config = Config {
+            circuit_breaker_window: 10,
+            circuit_breaker_min_orders: 4,
+            circuit_breaker_failure_rate: 0.5,
+            ..Config::default()
+        };
+        let start = clock::now();
+        let cooldown = Duration::from_millis(config.circuit_breaker_cooldown_millis);
+        let mut breaker = CircuitBreaker::default();
+
+        assert_eq!(breaker.record(true, start, &config), None);
+        assert_eq!(breaker.record(false, start, &config), None);
+        assert_eq!(breaker.record(true, start, &config), None);
+        assert_eq!(
+            breaker.record(false, start, &config),
+            Some(BreakerState::OPEN)
+        );
+        assert!(!breaker.allows(start, &config));
+
+        let later = start + cooldown;
+        assert!(breaker.allows(later, &config));
+        assert_eq!(breaker.request(later), Some(BreakerState::HALF_OPEN));
+        assert!(!breaker.allows(later, &config));
+        assert_eq!(
+            breaker.record(false, later, &config),
+            Some(BreakerState::OPEN)
+        );
+
+        let later = later + cooldown;
+        assert_eq!(breaker.request(later), Some(BreakerState::HALF_OPEN));
+        assert_eq!(
+            breaker.record(true, later, &config),
+            Some(BreakerState::CLOSED)
+        );
+        assert!(breaker.allows(later, &config));
+        assert_eq!(breaker.record(false, later, &config), None);
+    }
+
+    #[test]
+    fn test_one_outcome_per_request() {
+        let config = Config::default();
+        let now = clock::now();
+        let mut breaker = CircuitBreaker::default();
+        let mut outcomes = RequestOutcomes::default();
+
+        // un pedido entregado, con la entrega repetida, es una sola muestra
+        for state in [
+            OnlinePurchaseState::RESERVED,
+            OnlinePurchaseState::DELIVERED,
+            OnlinePurchaseState::DELIVERED,
+        ] {
+            if let Some(success) = outcomes.answer(1, &state) {
+                breaker.record(success, now, &config);
+            }
+        }
+        assert_eq!(breaker.outcomes, vec![true]);
+
+        assert_eq!(outcomes.answer(2, &OnlinePurchaseState::RESERVED), None);
+        assert_eq!(outcomes.answer(2, &OnlinePurchaseState::REJECTED), None);
+        assert_eq!(outcomes.answer(2, &OnlinePurchaseState::LOST), Some(false));
+        assert!(!outcomes.timed_out(2));
+
+        assert_eq!(
+            outcomes.answer(3, &OnlinePurchaseState::REJECTED),
+            Some(false)
+        );
+        assert!(outcomes.timed_out(4));
+        assert_eq!(outcomes.answer(4, &OnlinePurchaseState::REJECTED), None);
+
+        outcomes.requested(3);
+        assert_eq!(
+            outcomes.answer(3, &OnlinePurchaseState::REJECTED),
+            Some(false)
+        );
+    }
+}
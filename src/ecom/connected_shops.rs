@@ -8,12 +8,17 @@ use tokio::{io::WriteHalf, net::TcpStream, sync::Mutex};
 
 use crate::error::FileError;
 
+use super::{circuit_breaker::CircuitBreaker, heartbeat::Liveness};
+
 #[derive(Debug, Clone)]
 
 pub struct ConnectedShop {
     pub name: String,
     pub zone_id: i32,
     pub stream: Arc<Mutex<WriteHalf<TcpStream>>>,
+    pub catalog_version: Option<u32>, // Se conoce luego del Welcome de la tienda
+    pub liveness: Liveness,
+    pub breaker: CircuitBreaker,
 }
 
 impl ConnectedShop {
@@ -41,7 +46,6 @@ impl ConnectedShop {
 
         Ok(streams)
     }
-    // if let Ok(stream) = s
 }
 
 #[cfg(test)]
@@ -6,12 +6,12 @@ use tokio::io::AsyncWriteExt;
 
 use crate::error::StreamError;
 
-use super::{connected_shops::ConnectedShop, ecom_actor::Ecom};
+use super::ecom_actor::Ecom;
 
 /// Read Stop and Reconnect commands from stdin ("s<shop_id> and r<shop_id> respectively"), and send messages to the correspondent ecom actor in each case
 pub fn connection_handling(ecom: Addr<Ecom>) {
     std::thread::spawn(move || {
-        for line in std::io::stdin().lines().flatten() {
+        for line in std::io::stdin().lines().map_while(Result::ok) {
             let (command, shop_id) = match parse_command(line) {
                 Ok((command, shop_id)) => (command, shop_id),
                 Err(e) => {
@@ -91,15 +91,9 @@ impl Handler<Reconnect> for Ecom {
     fn handle(&mut self, msg: Reconnect, ctx: &mut Context<Self>) -> Self::Result {
         let zone_id = msg.0;
 
-        let streams = ConnectedShop::from_file("tiendas").map_err(|_| StreamError::CannotCall)?;
-
-        let mut new_shop = None;
-        for shop in streams {
-            if shop.1 == zone_id {
-                new_shop = Some(shop);
-                break;
-            }
-        }
+        let new_shop = self
+            .shop_address(zone_id)
+            .map_err(|_| StreamError::CannotCall)?;
 
         if let Some((name, zone_id, stream)) = new_shop {
             if self
@@ -1,49 +1,326 @@
+use crate::clock;
+use crate::config::Config;
 use crate::ecom::process_order::ProcessOrder;
 use crate::error::FileError;
+use crate::error::ProtocolError;
 use crate::error::PurchaseError;
 use crate::error::StreamError;
-use crate::states::OnlinePurchaseState;
+use crate::order_id::OrderId;
+use crate::order_item::{describe, quantity_of, OrderItem};
+use crate::protocol::codec::{framed_read, send_message};
+use crate::protocol::wire_message::WireMessage;
+use crate::states::{BreakerState, OnlinePurchaseState, ShopStatus};
+use crate::zones::ZoneMap;
+use actix::dev::ContextFutureSpawner;
+use actix::fut::wrap_future;
 use actix::{Actor, AsyncContext, Context, Message, StreamHandler};
 use colored::Colorize;
+use futures::{stream, StreamExt};
+use rand::{rngs::StdRng, SeedableRng};
 use std::collections::HashMap;
 use std::fs::File;
 use std::io::{BufRead, BufReader, Read};
 use std::sync::Arc;
-use std::vec;
-use tokio::io::split;
-use tokio::io::AsyncBufReadExt;
+use std::time::Duration;
+use tokio::io::{split, WriteHalf};
 use tokio::net::TcpStream;
 use tokio::sync::Mutex;
-use tokio_stream::wrappers;
 
+use super::circuit_breaker::{CircuitBreaker, RequestOutcomes};
 use super::connected_shops::ConnectedShop;
+use super::heartbeat::{Heartbeat, Liveness};
+use super::journal::{Journal, JournalEntry};
+use super::reconnect_shop::{reconnect_delay, ReconnectShop};
+use super::resume_orders::QueryCommitted;
+use super::routing::{NearestRouting, RoutingContext, RoutingStrategy};
+
+/// First field of the line that starts a cart in the orders file
+const CART_HEADER: &str = "pedido";
+
+/// Events read from the connection with a shop
+#[derive(Debug)]
+pub enum ShopEvent {
+    Message(Result<WireMessage, ProtocolError>),
+    /// The connection was closed. Carries the write half, to tell which connection it was
+    Closed(Arc<Mutex<WriteHalf<TcpStream>>>),
+}
+
+/// Portion of an order reserved by a shop
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShopReservation {
+    pub zone_id: i32,
+    pub items: Vec<OrderItem>,
+}
+
+/// Last request sent to a shop for an order
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShopRequest {
+    pub zone_id: i32,
+    pub items: Vec<OrderItem>,
+    pub allow_partial: bool,
+}
+
+/// What the ecom has to do with an order after a shop answered for it
+#[derive(Debug, PartialEq)]
+pub enum OrderStep {
+    /// Nothing, the order keeps waiting for the shops
+    Wait,
+    /// Every item is reserved, the reservations have to be committed
+    Commit,
+    /// There are items left, they have to be requested to another shop
+    Process,
+    /// Every item was delivered, or no shop is left and the committed parts finished
+    Done,
+}
+
 #[derive(Debug, Message, Clone)]
 #[rtype(result = "Result<(), PurchaseError>")]
 pub struct EcomOrder {
-    pub id: u32,
-    pub product_id: String,
-    pub quantity: u32,
+    pub id: OrderId,
+    pub items: Vec<OrderItem>,
     pub zone_id: i32,
     pub shops_requested: Vec<i32>,
+    /// Whether shops may reserve only a portion of the order
+    pub allow_partial: bool,
+    /// Prepared reservations, waiting for the whole order to be reserved to be committed
+    pub reservations: Vec<ShopReservation>,
+    /// Committed reservations, being delivered by the shops
+    pub committed: Vec<ShopReservation>,
+    pub delivered: Vec<OrderItem>,
+    /// Shops asked to cancel the order, waiting for their answer. Their reservations are ignored
+    pub cancelling_shops: Vec<i32>,
+    /// Last request sent to each shop, to ask the shops about the order again after a crash
+    pub requests: Vec<ShopRequest>,
+    /// Whether no shop is left for the outstanding items. The order only waits for its
+    /// committed parts to finish
+    pub exhausted: bool,
+    /// Answers of the shops to their latest request, recorded once in their circuit breakers
+    pub outcomes: RequestOutcomes,
 }
 
 impl EcomOrder {
-    pub fn as_string(&self) -> String {
-        format!(
-            "{},{},{},{}\n",
-            self.id, self.product_id, self.quantity, self.zone_id
-        )
+    pub fn new(id: OrderId, items: Vec<OrderItem>, zone_id: i32) -> Self {
+        Self {
+            id,
+            items,
+            zone_id,
+            shops_requested: Vec::new(),
+            allow_partial: false,
+            reservations: Vec::new(),
+            committed: Vec::new(),
+            delivered: Vec::new(),
+            cancelling_shops: Vec::new(),
+            requests: Vec::new(),
+            exhausted: false,
+            outcomes: RequestOutcomes::default(),
+        }
+    }
+
+    /// Items, with their quantities, that still have to be requested to a shop
+    pub fn outstanding(&self) -> Vec<OrderItem> {
+        self.items
+            .iter()
+            .map(|item| {
+                let taken = quantity_of(&self.delivered, &item.product)
+                    + self
+                        .reservations
+                        .iter()
+                        .chain(self.committed.iter())
+                        .map(|r| quantity_of(&r.items, &item.product))
+                        .sum::<u32>();
+                OrderItem::new(&item.product, item.quantity.saturating_sub(taken))
+            })
+            .filter(|item| item.quantity > 0)
+            .collect()
+    }
+
+    /// Whether the order has nothing else to wait for: every item was delivered, or no shop
+    /// is left and the committed parts finished
+    fn is_finished(&self) -> bool {
+        self.is_delivered() || (self.exhausted && self.committed.is_empty())
+    }
+
+    /// Whether every item of the order has been delivered
+    pub fn is_delivered(&self) -> bool {
+        self.items
+            .iter()
+            .all(|item| quantity_of(&self.delivered, &item.product) >= item.quantity)
+    }
+
+    /// Removes every reservation of the given shop, prepared or committed
+    pub fn drop_shop(&mut self, zone_id: i32) {
+        self.reservations.retain(|r| r.zone_id != zone_id);
+        self.committed.retain(|r| r.zone_id != zone_id);
+    }
+
+    /// Removes the given items from the committed reservation of the given shop, once the
+    /// shop finished delivering them
+    pub fn settle(&mut self, zone_id: i32, items: &[OrderItem]) {
+        if let Some(reservation) = self.committed.iter_mut().find(|r| r.zone_id == zone_id) {
+            for reserved in reservation.items.iter_mut() {
+                reserved.quantity = reserved
+                    .quantity
+                    .saturating_sub(quantity_of(items, &reserved.product));
+            }
+            reservation.items.retain(|item| item.quantity > 0);
+        }
+        self.committed.retain(|r| !r.items.is_empty());
+    }
+
+    /// Whether the order is waiting for the answer of the shop in the given zone
+    pub fn awaits(&self, zone_id: i32) -> bool {
+        self.shops_requested.last() == Some(&zone_id)
+            && !self.cancelling_shops.contains(&zone_id)
+            && !self.outstanding().is_empty()
+    }
+
+    /// Registers that the outstanding items of the order are requested to the given shop
+    pub fn request_shop(&mut self, zone_id: i32) {
+        self.shops_requested.push(zone_id);
+        self.outcomes.requested(zone_id);
+        self.requests.retain(|request| request.zone_id != zone_id);
+        self.requests.push(ShopRequest {
+            zone_id,
+            items: self.outstanding(),
+            allow_partial: self.allow_partial,
+        });
+    }
+
+    /// Moves the prepared reservations to the committed ones, returning them
+    pub fn commit_reservations(&mut self) -> Vec<ShopReservation> {
+        let reservations = std::mem::take(&mut self.reservations);
+        self.committed.extend(reservations.iter().cloned());
+        reservations
+    }
+
+    /// Applies the state answered by the shop in the given zone to the order
+    pub fn apply_state(
+        &mut self,
+        shop_zone: i32,
+        state: OnlinePurchaseState,
+        items: Vec<OrderItem>,
+    ) -> OrderStep {
+        let committed = self.committed.iter().any(|r| r.zone_id == shop_zone);
+
+        if self.cancelling_shops.contains(&shop_zone) {
+            match state {
+                OnlinePurchaseState::CANCELLED => {
+                    self.cancelling_shops.retain(|zone| *zone != shop_zone);
+                    return OrderStep::Wait;
+                }
+                OnlinePurchaseState::TOO_LATE => {
+                    // la tienda ya esta entregando, se espera su entrega
+                    self.cancelling_shops.retain(|zone| *zone != shop_zone);
+                    self.committed.push(ShopReservation {
+                        zone_id: shop_zone,
+                        items,
+                    });
+                    return OrderStep::Wait;
+                }
+                // la tienda libera esta reserva al recibir la cancelacion
+                OnlinePurchaseState::RESERVED
+                | OnlinePurchaseState::PARTIAL
+                | OnlinePurchaseState::REJECTED => return OrderStep::Wait,
+                _ => {}
+            }
+        }
+
+        match state {
+            OnlinePurchaseState::CANCELLED | OnlinePurchaseState::TOO_LATE => OrderStep::Wait,
+            // la tienda repite la reserva que ya esta entregando
+            OnlinePurchaseState::RESERVED | OnlinePurchaseState::PARTIAL if committed => {
+                OrderStep::Wait
+            }
+            OnlinePurchaseState::RESERVED | OnlinePurchaseState::PARTIAL => {
+                // una respuesta repetida de la tienda reemplaza a la anterior
+                self.reservations.retain(|r| r.zone_id != shop_zone);
+                self.reservations.push(ShopReservation {
+                    zone_id: shop_zone,
+                    items,
+                });
+                if self.outstanding().is_empty() {
+                    OrderStep::Commit
+                } else {
+                    OrderStep::Process
+                }
+            }
+            // la entrega ya se registro
+            OnlinePurchaseState::DELIVERED | OnlinePurchaseState::LOST if !committed => {
+                OrderStep::Wait
+            }
+            OnlinePurchaseState::DELIVERED => {
+                self.settle(shop_zone, &items);
+                self.delivered.extend(items);
+                if self.is_finished() {
+                    OrderStep::Done
+                } else {
+                    OrderStep::Wait
+                }
+            }
+            OnlinePurchaseState::LOST => {
+                self.settle(shop_zone, &items);
+                match (self.is_finished(), self.exhausted) {
+                    (true, _) => OrderStep::Done,
+                    (false, true) => OrderStep::Wait,
+                    (false, false) => OrderStep::Process,
+                }
+            }
+            _ => {
+                // la tienda pudo haber abortado una reserva por timeout
+                self.drop_shop(shop_zone);
+                OrderStep::Process
+            }
+        }
+    }
+
+    /// Builds the message sent to the shops for the outstanding items of this order
+    pub fn to_message(&self) -> WireMessage {
+        WireMessage::Prepare {
+            id: self.id.clone(),
+            items: self.outstanding(),
+            zone_id: self.zone_id,
+            allow_partial: self.allow_partial,
+        }
     }
 }
 
 #[derive(Debug)]
 pub struct Ecom {
     pub name: String,
-    pub pending_orders: HashMap<u32, EcomOrder>,
+    pub pending_orders: HashMap<OrderId, EcomOrder>,
     pub shops: Vec<ConnectedShop>,
+    pub routing: Box<dyn RoutingStrategy>,
+    pub zones: ZoneMap,
+    pub config: Config,
+    /// Random number generator for the processing delays, routing and reconnections
+    pub rng: StdRng,
+    /// Journal where the events of the orders are written, if any
+    pub journal: Option<Journal>,
+    /// Name, zone id and address of the shops to connect to, instead of the ones in the
+    /// shop files
+    pub addresses: Option<Vec<(String, i32, String)>>,
+    /// Orders already delivered or cancelled, kept only for the integration tests
+    #[cfg(feature = "harness")]
+    pub finished_orders: Vec<EcomOrder>,
 }
 
 impl Ecom {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            pending_orders: HashMap::new(),
+            shops: Vec::new(),
+            routing: Box::new(NearestRouting),
+            zones: ZoneMap::default(),
+            config: Config::default(),
+            rng: StdRng::from_entropy(),
+            journal: None,
+            addresses: None,
+            #[cfg(feature = "harness")]
+            finished_orders: Vec::new(),
+        }
+    }
+
     /// Reads the ecom info from the file in the given path
     pub fn from_file(path: &str) -> Result<Self, FileError> {
         let file = File::open(path).map_err(|_| FileError::NotFound)?;
@@ -69,69 +346,107 @@ impl Ecom {
             return Err(FileError::WrongFormat);
         }
 
-        let ecom = Self {
-            name: ecom_info[0].to_string(),
-            pending_orders: HashMap::new(),
-            shops: Vec::new(),
-        };
-
-        Ok(ecom)
+        Ok(Self::new(ecom_info[0]))
     }
 
     /// Reads the orders from the file in the given path
     pub fn orders_from_file(path: &str) -> Result<Vec<EcomOrder>, FileError> {
         let file = File::open(path).map_err(|_| FileError::NotFound)?;
-        let reader = BufReader::new(file);
+        Self::orders_from_reader(file)
+    }
+
+    /// Reads the orders from the given reader. Each order is either a single line with
+    /// `<product>,<quantity>,<zone>`, or a cart: a `pedido,<zone>` header followed by
+    /// one `<product>,<quantity>` line per item.
+    fn orders_from_reader<T: Read>(content: T) -> Result<Vec<EcomOrder>, FileError> {
+        let reader = BufReader::new(content);
 
         let mut orders: Vec<EcomOrder> = Vec::new();
+        let mut cart: Option<EcomOrder> = None;
         let mut lines = reader.lines();
 
-        // ignore info line
-        lines.next();
+        // el nombre del ecom forma parte del id de cada pedido
+        let ecom_name = match lines.next() {
+            Some(line) => line.map_err(|_| FileError::WrongFormat)?,
+            None => return Err(FileError::WrongFormat),
+        };
         // ignore dash line
         lines.next();
 
-        for (line_number, line) in lines.enumerate() {
+        for line in lines {
             let current_line = line.map_err(|_| FileError::WrongFormat)?;
 
             let product_data: Vec<&str> = current_line.split(',').collect();
 
-            // ['KEY', 'VALUE', 'ZONE'].len() == 3
-            if product_data.len() != 3 {
-                return Err(FileError::WrongFormat);
+            match product_data.as_slice() {
+                // ['pedido', 'ZONE'] empieza un carrito
+                [CART_HEADER, zone] => {
+                    orders.extend(Self::close_cart(cart.take())?);
+                    let sequence = orders.len() as u32;
+                    cart = Some(EcomOrder::new(
+                        OrderId::new(&ecom_name, sequence),
+                        Vec::new(),
+                        zone.parse().map_err(|_| FileError::WrongFormat)?,
+                    ));
+                }
+                // ['KEY', 'VALUE'] es un item del carrito actual
+                [product, quantity] => {
+                    let order = cart.as_mut().ok_or(FileError::WrongFormat)?;
+                    order.items.push(OrderItem::new(
+                        product,
+                        quantity.parse().map_err(|_| FileError::WrongFormat)?,
+                    ));
+                }
+                // ['KEY', 'VALUE', 'ZONE'] es un pedido de un solo producto
+                [product, quantity, zone] => {
+                    orders.extend(Self::close_cart(cart.take())?);
+                    let sequence = orders.len() as u32;
+                    orders.push(EcomOrder::new(
+                        OrderId::new(&ecom_name, sequence),
+                        vec![OrderItem::new(
+                            product,
+                            quantity.parse().map_err(|_| FileError::WrongFormat)?,
+                        )],
+                        zone.parse().map_err(|_| FileError::WrongFormat)?,
+                    ));
+                }
+                _ => return Err(FileError::WrongFormat),
             }
-            let ecom_order = EcomOrder {
-                id: line_number as u32,
-                product_id: product_data[0].to_string(),
-                quantity: product_data[1]
-                    .parse()
-                    .map_err(|_| FileError::WrongFormat)?,
-                zone_id: product_data[2]
-                    .parse()
-                    .map_err(|_| FileError::WrongFormat)?,
-                shops_requested: vec![],
-            };
-
-            orders.push(ecom_order);
         }
+        orders.extend(Self::close_cart(cart)?);
 
         Ok(orders)
     }
 
-    /// Returns the next shop, if any, to deliver the order or None if there are no more shops available.
-    pub fn find_delivery_shop(&self, order: &EcomOrder) -> Option<ConnectedShop> {
-        let mut shops = self.shops.clone();
-        shops.sort_by(|a, b| {
-            if (a.zone_id - order.zone_id).abs() > (b.zone_id - order.zone_id).abs() {
-                std::cmp::Ordering::Greater
-            } else {
-                std::cmp::Ordering::Less
-            }
-        });
+    /// Checks that the finished cart, if any, has at least one item
+    fn close_cart(cart: Option<EcomOrder>) -> Result<Option<EcomOrder>, FileError> {
+        match cart {
+            Some(order) if order.items.is_empty() => Err(FileError::WrongFormat),
+            cart => Ok(cart),
+        }
+    }
 
-        shops
-            .into_iter()
-            .find(|shop| !order.shops_requested.contains(&shop.zone_id))
+    /// Returns the next shop, if any, to deliver the order or None if there are no more shops available.
+    /// The shop is chosen by the routing strategy among the shops not requested yet for the order,
+    /// leaving out the shops that are DOWN or whose circuit breaker does not allow more orders.
+    pub fn find_delivery_shop(&mut self, order: &EcomOrder) -> Option<ConnectedShop> {
+        let now = clock::now();
+        let candidates = self
+            .shops
+            .iter()
+            .filter(|shop| !order.shops_requested.contains(&shop.zone_id))
+            .filter(|shop| shop.liveness.status != ShopStatus::DOWN)
+            .filter(|shop| shop.breaker.allows(now, &self.config))
+            .cloned()
+            .collect();
+
+        self.routing.select_shop(RoutingContext {
+            order,
+            candidates,
+            pending_orders: &self.pending_orders,
+            zones: &self.zones,
+            rng: &mut self.rng,
+        })
     }
 
     /// Tries to stablish a connection with the shop with the given zone id
@@ -147,69 +462,380 @@ impl Ecom {
             Err(_) => return Err(StreamError::CannotCall),
         };
 
+        // tokio necesita el socket en modo no bloqueante, sino las lecturas bloquean al actor
+        if stream_std.set_nonblocking(true).is_err() {
+            return Err(StreamError::CannotCall);
+        }
+
         let stream = match TcpStream::from_std(stream_std) {
             Ok(stream) => stream,
             Err(_) => return Err(StreamError::CannotCall),
         };
 
         let (read, write_half) = split(stream);
+        let stream = Arc::new(Mutex::new(write_half));
+
+        let closed = ShopEvent::Closed(stream.clone());
         Ecom::add_stream(
-            wrappers::LinesStream::new(tokio::io::BufReader::new(read).lines()),
+            framed_read(read)
+                .map(ShopEvent::Message)
+                .chain(stream::once(async { closed }))
+                .map(move |event| (zone_id, event)),
             ctx,
         );
 
         self.shops.push(ConnectedShop {
             name,
             zone_id,
-            stream: Arc::new(Mutex::new(write_half)),
+            stream: stream.clone(),
+            catalog_version: None,
+            liveness: Liveness::default(),
+            breaker: CircuitBreaker::default(),
         });
 
+        let hello = WireMessage::Hello {
+            ecom: self.name.clone(),
+        };
+        wrap_future::<_, Self>(async move {
+            if send_message(&stream, hello).await.is_err() {
+                println!(
+                    "{} No se pudo identificar el ecom ante la tienda {}",
+                    "[ECOM]".purple(),
+                    zone_id
+                );
+            }
+        })
+        .wait(ctx);
+
         Ok(())
     }
+
+    /// Registers the identity received from a shop after the Hello
+    fn welcome(&mut self, shop_name: String, zone_id: i32, catalog_version: u32) {
+        let shop = match self.shops.iter_mut().find(|shop| shop.zone_id == zone_id) {
+            Some(shop) => shop,
+            None => return,
+        };
+        shop.catalog_version = Some(catalog_version);
+        println!(
+            "{} Tienda {} ({}) identificada, catalogo v{}",
+            "[ECOM]".purple(),
+            zone_id,
+            shop_name,
+            catalog_version
+        );
+    }
+
+    /// Sends the given message to the connected shop with the given zone id
+    pub fn send_to_shop(&self, zone_id: i32, message: WireMessage, ctx: &mut Context<Ecom>) {
+        let stream = match self.shops.iter().find(|shop| shop.zone_id == zone_id) {
+            Some(shop) => shop.stream.clone(),
+            None => {
+                println!("{} Tienda {} no conectada", "[ECOM]".purple(), zone_id);
+                return;
+            }
+        };
+
+        wrap_future::<_, Self>(async move {
+            if send_message(&stream, message).await.is_err() {
+                println!(
+                    "{} No se pudo enviar el mensaje a la tienda {}",
+                    "[ECOM]".purple(),
+                    zone_id
+                );
+            }
+        })
+        .wait(ctx);
+    }
+
+    /// Writes the entry to the journal before acting on it. The ecom keeps working if the
+    /// journal cannot be written, but its orders could not be recovered
+    pub fn journal(&mut self, entry: JournalEntry) {
+        if let Some(journal) = self.journal.as_mut() {
+            if journal.append(&entry).is_err() {
+                println!("{} Error al escribir el diario", "[ECOM]".purple());
+            }
+        }
+    }
+
+    /// Removes the order from the pending ones, once delivered or cancelled
+    pub fn finish_order(&mut self, id: &OrderId) {
+        self.journal(JournalEntry::Finished {
+            sequence: id.sequence,
+        });
+        if let Some(_order) = self.pending_orders.remove(id) {
+            #[cfg(feature = "harness")]
+            self.finished_orders.push(_order);
+        }
+    }
+
+    /// Finishes the order for which no shop is left, once its committed parts finished. It
+    /// is reported as partial if some of its items were delivered, otherwise as cancelled
+    pub fn give_up_order(&mut self, id: &OrderId) {
+        let order = match self.pending_orders.get(id) {
+            Some(order) => order,
+            None => return,
+        };
+        if order.delivered.is_empty() {
+            println!(
+                "{} Pedido {} {}: {} (No hay mas tiendas)",
+                "[ECOM]".purple(),
+                id,
+                "CANCELADO".on_red(),
+                describe(&order.outstanding())
+            );
+        } else {
+            println!(
+                "{} Pedido {} {}: {} (No hay mas tiendas para {})",
+                "[ECOM]".purple(),
+                id,
+                "ENTREGA PARCIAL".on_yellow(),
+                describe(&order.delivered),
+                describe(&order.outstanding())
+            );
+        }
+        self.finish_order(id);
+    }
+
+    /// Name, zone id and address of every shop the ecom can connect to
+    pub fn shop_addresses(&self) -> Result<Vec<(String, i32, String)>, FileError> {
+        match &self.addresses {
+            Some(addresses) => Ok(addresses.clone()),
+            None => ConnectedShop::from_file(&self.config.shops_path),
+        }
+    }
+
+    /// Name, zone id and address of the shop with the given zone id, if any
+    pub fn shop_address(&self, zone_id: i32) -> Result<Option<(String, i32, String)>, FileError> {
+        Ok(self
+            .shop_addresses()?
+            .into_iter()
+            .find(|shop| shop.1 == zone_id))
+    }
+
+    /// Commits every prepared reservation of the order, so the shops deliver them. If the
+    /// commit or the delivery gets lost, the shops are asked for the state of the order
+    /// until they answer
+    pub fn commit_order(&mut self, id: &OrderId, ctx: &mut Context<Ecom>) {
+        let reservations = match self.pending_orders.get_mut(id) {
+            Some(order) => order.commit_reservations(),
+            None => return,
+        };
+
+        for reservation in reservations {
+            self.send_to_shop(
+                reservation.zone_id,
+                WireMessage::Commit { id: id.clone() },
+                ctx,
+            );
+        }
+        ctx.notify_later(
+            QueryCommitted(id.clone()),
+            Duration::from_millis(self.config.ecom_max_waiting_millis),
+        );
+    }
+
+    /// Aborts every prepared reservation of the order, so the shops get their stock back
+    pub fn abort_order(&mut self, id: &OrderId, ctx: &mut Context<Ecom>) {
+        let reservations = match self.pending_orders.get_mut(id) {
+            Some(order) => std::mem::take(&mut order.reservations),
+            None => return,
+        };
+
+        for reservation in reservations {
+            self.send_to_shop(
+                reservation.zone_id,
+                WireMessage::Abort { id: id.clone() },
+                ctx,
+            );
+        }
+    }
+
+    /// Registers that a frame was received from the shop in the given zone
+    fn shop_seen(&mut self, zone_id: i32) {
+        let shop = match self.shops.iter_mut().find(|shop| shop.zone_id == zone_id) {
+            Some(shop) => shop,
+            None => return,
+        };
+        if shop.liveness.seen(clock::now()) {
+            println!(
+                "{} Tienda {} {}",
+                "[ECOM]".purple(),
+                zone_id,
+                ShopStatus::ALIVE.string_to_print()
+            );
+        }
+    }
+
+    /// Registers in the circuit breaker of the shop in the given zone that an order was sent
+    /// to it
+    pub fn shop_requested(&mut self, zone_id: i32) {
+        if let Some(shop) = self.shops.iter_mut().find(|shop| shop.zone_id == zone_id) {
+            if let Some(state) = shop.breaker.request(clock::now()) {
+                Self::print_breaker(zone_id, state);
+            }
+        }
+    }
+
+    /// Registers in the circuit breaker of the shop in the given zone the outcome of an order
+    pub fn shop_outcome(&mut self, zone_id: i32, success: bool) {
+        if let Some(shop) = self.shops.iter_mut().find(|shop| shop.zone_id == zone_id) {
+            if let Some(state) = shop.breaker.record(success, clock::now(), &self.config) {
+                Self::print_breaker(zone_id, state);
+            }
+        }
+    }
+
+    fn print_breaker(zone_id: i32, state: BreakerState) {
+        println!(
+            "{} Circuito de la tienda {} {}",
+            "[ECOM]".purple(),
+            zone_id,
+            state.string_to_print()
+        );
+    }
+
+    /// Removes the shop whose connection was closed and schedules its reconnection, unless
+    /// the connection was already replaced or stopped from the command line
+    fn shop_closed(
+        &mut self,
+        zone_id: i32,
+        stream: Arc<Mutex<WriteHalf<TcpStream>>>,
+        ctx: &mut Context<Ecom>,
+    ) {
+        let is_current = self
+            .shops
+            .iter()
+            .any(|shop| shop.zone_id == zone_id && Arc::ptr_eq(&shop.stream, &stream));
+        if !is_current {
+            return;
+        }
+        self.shops.retain(|shop| shop.zone_id != zone_id);
+
+        let in_flight = self
+            .pending_orders
+            .values()
+            .filter(|order| order.awaits(zone_id))
+            .map(|order| order.id.clone())
+            .collect();
+
+        println!(
+            "{} Se perdio la conexion con la tienda {}",
+            "[ECOM]".purple(),
+            zone_id
+        );
+        ctx.notify_later(
+            ReconnectShop {
+                zone_id,
+                attempt: 0,
+                in_flight,
+            },
+            reconnect_delay(0, &self.config, &mut self.rng),
+        );
+    }
+
+    /// Updates the order with the state received from the shop in the given zone
+    fn update_order(
+        &mut self,
+        shop_zone: i32,
+        id: OrderId,
+        state: OnlinePurchaseState,
+        items: Vec<OrderItem>,
+        ctx: &mut Context<Ecom>,
+    ) {
+        // las respuestas a una cancelacion no dicen nada de la salud de la tienda
+        let outcome = match self.pending_orders.get_mut(&id) {
+            Some(order) if order.cancelling_shops.contains(&shop_zone) => None,
+            Some(order) => order.outcomes.answer(shop_zone, &state),
+            None => return, // El pedido ya fue entregado o cancelado, alargue el timeout
+        };
+        self.journal(JournalEntry::State {
+            sequence: id.sequence,
+            zone_id: shop_zone,
+            state: state.clone(),
+            items: items.clone(),
+        });
+        if let Some(success) = outcome {
+            self.shop_outcome(shop_zone, success);
+        }
+        let order = match self.pending_orders.get_mut(&id) {
+            Some(order) => order,
+            None => return,
+        };
+
+        println!(
+            "{} Pedido {} {}: {}",
+            format!("[TIENDA {}]", shop_zone).blue(),
+            id,
+            state.string_to_print(),
+            describe(&items)
+        );
+
+        match order.apply_state(shop_zone, state, items) {
+            OrderStep::Wait => {}
+            OrderStep::Commit => self.commit_order(&id, ctx),
+            OrderStep::Process => ctx.address().do_send(ProcessOrder(order.clone())),
+            OrderStep::Done if order.exhausted => self.give_up_order(&id),
+            OrderStep::Done => self.finish_order(&id),
+        }
+    }
 }
 
 impl Actor for Ecom {
     type Context = Context<Self>;
-}
 
-impl StreamHandler<Result<String, std::io::Error>> for Ecom {
-    /// Handles the messages received from the shops for each order
-    /// If the order is DELIVERED, it removes it from the pending orders
-    /// If the order is not DELIVERED, it sends a ProcessOrder message to the ecom
-    fn handle(&mut self, read: Result<String, std::io::Error>, ctx: &mut Self::Context) {
-        if let Ok(line) = read {
-            let order_str = line.split(',').collect::<Vec<&str>>();
-            let id = match order_str[0].parse::<u32>() {
-                Ok(id) => id,
-                Err(_) => return,
-            };
-            let state_number = match order_str[1].parse::<u8>() {
-                Ok(state_number) => state_number,
-                Err(_) => return,
-            };
-            let state = match OnlinePurchaseState::from_int(state_number) {
-                Some(state) => state,
-                None => return,
-            };
-
-            let order = match self.pending_orders.get(&id) {
-                Some(order) => order,
-                None => return, // El pedido ya fue entregado o cancelado, alargue el timeout
-            };
+    fn started(&mut self, ctx: &mut Context<Self>) {
+        ctx.run_interval(
+            Duration::from_millis(self.config.heartbeat_interval_millis),
+            |_ecom, ctx| ctx.notify(Heartbeat),
+        );
+    }
+}
 
-            println!(
-                "{} Pedido {}: {:<2}x {}",
-                format!("[TIENDA {}]", order.shops_requested.last().unwrap_or(&-1)).blue(),
-                state.string_to_print(),
-                order.quantity,
-                order.product_id
-            );
+impl StreamHandler<(i32, ShopEvent)> for Ecom {
+    /// Handles the messages received from the shop in the given zone for each order
+    /// If the order is RESERVED or PARTIAL and the whole order is reserved, it commits the
+    /// reservations of the order; otherwise it sends a ProcessOrder message for the rest
+    /// If the whole order is DELIVERED, it removes it from the pending orders
+    /// If the order is REJECTED or LOST, it sends a ProcessOrder message to the ecom
+    /// for the quantity that is still missing
+    /// Answers from shops asked to cancel the order only matter if they are TOO_LATE, since
+    /// then the shop is delivering its part
+    /// Restocks of the shop are only reported
+    /// If the connection with the shop closes, it tries to reconnect it
+    fn handle(&mut self, (zone_id, event): (i32, ShopEvent), ctx: &mut Self::Context) {
+        let read = match event {
+            ShopEvent::Message(read) => read,
+            ShopEvent::Closed(stream) => return self.shop_closed(zone_id, stream, ctx),
+        };
+        self.shop_seen(zone_id);
 
-            match state {
-                OnlinePurchaseState::DELIVERED => {
-                    self.pending_orders.remove_entry(&id);
-                }
-                _ => ctx.address().do_send(ProcessOrder(order.clone())),
+        match read {
+            Ok(WireMessage::OrderState { id, state, items }) => {
+                self.update_order(zone_id, id, state, items, ctx)
+            }
+            Ok(WireMessage::Pong) => {}
+            Ok(WireMessage::Welcome {
+                shop,
+                zone_id,
+                catalog_version,
+            }) => {
+                self.welcome(shop, zone_id, catalog_version);
+            }
+            Ok(WireMessage::Restocked { shop, items }) => {
+                println!(
+                    "{} Tienda {} ({}) repuso: {}",
+                    "[ECOM]".purple(),
+                    zone_id,
+                    shop,
+                    describe(&items)
+                );
+            }
+            Ok(message) => {
+                println!("{} Mensaje inesperado: {:?}", "[ECOM]".purple(), message);
+            }
+            Err(error) => {
+                println!("{} Mensaje invalido: {:?}", "[ECOM]".purple(), error);
             }
         }
     }
@@ -220,7 +846,7 @@ impl StreamHandler<Result<String, std::io::Error>> for Ecom {
 #[cfg(test)]
 mod tests {
 
-    use std::{sync::Arc, thread, time::Duration};
+    use std::{sync::Arc, thread};
     use tokio::{io::split, net::TcpStream, sync::Mutex};
 
     use crate::ecom::ecom_actor::EcomOrder;
@@ -230,12 +856,12 @@ mod tests {
 
     #[actix_rt::test]
     async fn test_find_delivery_zone() {
+        let listener = std::net::TcpListener::bind("localhost:0").unwrap();
+        let address = listener.local_addr().unwrap();
         thread::spawn(move || {
-            let listener = std::net::TcpListener::bind("localhost:12355").unwrap();
             listener.accept().unwrap();
         });
-        thread::sleep(Duration::from_millis(100));
-        let stream = std::net::TcpStream::connect("localhost:12355").unwrap();
+        let stream = std::net::TcpStream::connect(address).unwrap();
         let tokio_stream = TcpStream::from_std(stream).unwrap();
         let (_read, write) = split(tokio_stream);
         let write = Arc::new(Mutex::new(write));
@@ -244,57 +870,42 @@ mod tests {
                 name: "retiro".to_string(),
                 zone_id: 1,
                 stream: write.clone(),
+                catalog_version: None,
+                liveness: Liveness::default(),
+                breaker: CircuitBreaker::default(),
             },
             ConnectedShop {
                 name: "palermo".to_string(),
                 zone_id: 5,
                 stream: write.clone(),
+                catalog_version: None,
+                liveness: Liveness::default(),
+                breaker: CircuitBreaker::default(),
             },
             ConnectedShop {
                 name: "recoleta".to_string(),
                 zone_id: 11,
                 stream: write.clone(),
+                catalog_version: None,
+                liveness: Liveness::default(),
+                breaker: CircuitBreaker::default(),
             },
             ConnectedShop {
                 name: "belgrano".to_string(),
                 zone_id: 20,
                 stream: write,
+                catalog_version: None,
+                liveness: Liveness::default(),
+                breaker: CircuitBreaker::default(),
             },
         ];
-        let ecom = Ecom {
-            name: String::from("ecom"),
-            pending_orders: HashMap::new(),
-            shops: conneted_shops,
-        };
+        let mut ecom = Ecom::new("ecom");
+        ecom.shops = conneted_shops;
 
-        let order1 = EcomOrder {
-            id: 1,
-            product_id: String::from("1"),
-            quantity: 1,
-            zone_id: 1,
-            shops_requested: vec![],
-        };
-        let order4 = EcomOrder {
-            id: 1,
-            product_id: String::from("1"),
-            quantity: 1,
-            zone_id: 4,
-            shops_requested: vec![],
-        };
-        let order7 = EcomOrder {
-            id: 1,
-            product_id: String::from("1"),
-            quantity: 1,
-            zone_id: 7,
-            shops_requested: vec![],
-        };
-        let order15 = EcomOrder {
-            id: 1,
-            product_id: String::from("1"),
-            quantity: 1,
-            zone_id: 15,
-            shops_requested: vec![],
-        };
+        let order1 = EcomOrder::new(OrderId::new("ecom", 1), vec![OrderItem::new("1", 1)], 1);
+        let order4 = EcomOrder::new(OrderId::new("ecom", 1), vec![OrderItem::new("1", 1)], 4);
+        let order7 = EcomOrder::new(OrderId::new("ecom", 1), vec![OrderItem::new("1", 1)], 7);
+        let order15 = EcomOrder::new(OrderId::new("ecom", 1), vec![OrderItem::new("1", 1)], 15);
         let shop1 = ecom.find_delivery_shop(&order1).unwrap();
         let shop4 = ecom.find_delivery_shop(&order4).unwrap();
         let shop7 = ecom.find_delivery_shop(&order7).unwrap();
@@ -305,4 +916,100 @@ mod tests {
         assert_eq!(shop7.zone_id, 5);
         assert_eq!(shop15.zone_id, 11);
     }
+
+    #[test]
+    fn test_orders_from_file_ids_include_ecom_name() {
+        let orders = Ecom::orders_from_file("pedidos/ecom1.txt").unwrap();
+
+        assert_eq!(orders[0].id, OrderId::new("ecom1", 0));
+        assert_eq!(orders[1].id, OrderId::new("ecom1", 1));
+    }
+
+    #[test]
+    fn test_orders_from_reader_with_carts() {
+        let content = "ecom2\n---\npedido,3\nmanzana,2\nbanana,1\ncoca,4,1\npedido,2\nhuevo,6\n";
+
+        let orders = Ecom::orders_from_reader(content.as_bytes()).unwrap();
+
+        assert_eq!(orders.len(), 3);
+        assert_eq!(orders[0].id, OrderId::new("ecom2", 0));
+        assert_eq!(
+            orders[0].items,
+            vec![OrderItem::new("manzana", 2), OrderItem::new("banana", 1)]
+        );
+        assert_eq!(orders[0].zone_id, 3);
+        assert_eq!(orders[1].id, OrderId::new("ecom2", 1));
+        assert_eq!(orders[1].items, vec![OrderItem::new("coca", 4)]);
+        assert_eq!(orders[2].id, OrderId::new("ecom2", 2));
+        assert_eq!(orders[2].items, vec![OrderItem::new("huevo", 6)]);
+    }
+
+    #[test]
+    fn test_orders_from_reader_wrong_carts() {
+        let item_without_cart = "ecom2\n---\nmanzana,2\n";
+        let empty_cart = "ecom2\n---\npedido,3\ncoca,4,1\n";
+
+        assert!(Ecom::orders_from_reader(item_without_cart.as_bytes()).is_err());
+        assert!(Ecom::orders_from_reader(empty_cart.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_outstanding_items() {
+        let mut order = EcomOrder::new(
+            OrderId::new("ecom", 1),
+            vec![OrderItem::new("manzana", 10), OrderItem::new("banana", 2)],
+            1,
+        );
+        order.reservations.push(ShopReservation {
+            zone_id: 1,
+            items: vec![OrderItem::new("manzana", 3), OrderItem::new("banana", 2)],
+        });
+        order.committed.push(ShopReservation {
+            zone_id: 2,
+            items: vec![OrderItem::new("manzana", 4)],
+        });
+        order.delivered.push(OrderItem::new("manzana", 2));
+
+        assert_eq!(order.outstanding(), vec![OrderItem::new("manzana", 1)]);
+
+        order.settle(2, &[OrderItem::new("manzana", 4)]);
+        order.drop_shop(1);
+        assert!(order.committed.is_empty());
+        assert_eq!(
+            order.outstanding(),
+            vec![OrderItem::new("manzana", 8), OrderItem::new("banana", 2)]
+        );
+        assert!(!order.is_delivered());
+    }
+
+    #[test]
+    fn test_exhausted_order_waits_for_committed_parts() {
+        let mut order = EcomOrder::new(
+            OrderId::new("ecom", 1),
+            vec![OrderItem::new("manzana", 10)],
+            1,
+        );
+        for zone_id in [1, 2] {
+            order.committed.push(ShopReservation {
+                zone_id,
+                items: vec![OrderItem::new("manzana", 3)],
+            });
+        }
+        order.exhausted = true;
+
+        let step = order.apply_state(
+            1,
+            OnlinePurchaseState::DELIVERED,
+            vec![OrderItem::new("manzana", 3)],
+        );
+        assert_eq!(step, OrderStep::Wait);
+
+        let step = order.apply_state(
+            2,
+            OnlinePurchaseState::LOST,
+            vec![OrderItem::new("manzana", 3)],
+        );
+        assert_eq!(step, OrderStep::Done);
+        assert_eq!(order.delivered, vec![OrderItem::new("manzana", 3)]);
+    }
 }
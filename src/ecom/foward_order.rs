@@ -1,16 +1,21 @@
 use std::time::Duration;
 
-use crate::{constants::ECOM_MAX_WAITING_MILLIS, ecom::process_order::ProcessOrder};
+use crate::{
+    clock,
+    ecom::process_order::ProcessOrder,
+    order_item::describe,
+    protocol::{codec::send_message, wire_message::WireMessage},
+};
 use actix::{
     dev::ContextFutureSpawner, fut::wrap_future, ActorFutureExt, AsyncContext, Handler, Message,
     ResponseActFuture, WrapFuture,
 };
 use colored::Colorize;
-use tokio::{io::AsyncWriteExt, time::sleep};
 
 use super::{
     connected_shops::ConnectedShop,
     ecom_actor::{Ecom, EcomOrder},
+    journal::JournalEntry,
 };
 
 #[derive(Debug, Message)]
@@ -25,22 +30,23 @@ impl Handler<FowardOrder> for Ecom {
 
     fn handle(&mut self, msg: FowardOrder, ctx: &mut Self::Context) -> Self::Result {
         println!(
-            "{} Enviando pedido a tienda en [{:?}]: {:<2}x {}",
+            "{} Enviando pedido {} a tienda en [{:?}]: {}",
             "[ECOM]".purple(),
+            msg.order.id,
             msg.shop.zone_id,
-            msg.order.quantity,
-            msg.order.product_id
+            describe(&msg.order.outstanding())
         );
 
-        let message = msg.order.as_string();
+        let message = msg.order.to_message();
+        let stream = msg.shop.stream.clone();
+        let zone_id = msg.shop.zone_id;
 
         wrap_future::<_, Self>(async move {
-            let mut write = msg.shop.stream.lock().await;
-            if write.write_all(message.as_bytes()).await.is_err() {
+            if send_message(&stream, message).await.is_err() {
                 println!(
                     "{} No se pudo enviar el pedido a la tienda en [{:?}]",
                     "[ECOM]".purple(),
-                    msg.shop.zone_id
+                    zone_id
                 );
             };
         })
@@ -48,23 +54,144 @@ impl Handler<FowardOrder> for Ecom {
 
         // timeout de perdida de pedido
         // solo se reenvia a otro si:
-        //    - no se entrego y
+        //    - no se entrego,
+        //    - la tienda no respondio por los items pedidos y
         //    - no se mando el pedido a ninguna tienda mas
         // o sea, este pedido esta "perdido"
-        return Box::pin(
-            sleep(Duration::from_millis(ECOM_MAX_WAITING_MILLIS))
+        Box::pin(
+            clock::sleep(Duration::from_millis(self.config.ecom_max_waiting_millis))
                 .into_actor(self)
                 .map(move |_, ecom, ctx| {
-                    let order = match ecom.pending_orders.get(&msg.order.id) {
+                    let order = match ecom.pending_orders.get_mut(&msg.order.id) {
                         Some(order) => order,
                         None => return, // no es mas pendiente, ya se entrego o fue cancelada por no haber mas tiendas
                     };
 
-                    if msg.shop.zone_id == *order.shops_requested.last().unwrap_or(&-1) {
-                        println!("[ECOM] PERDIDO  {}x {}", order.quantity, order.product_id);
-                        ctx.address().do_send(ProcessOrder(order.clone()));
+                    if msg.shop.zone_id == *order.shops_requested.last().unwrap_or(&-1)
+                        && !order.outstanding().is_empty()
+                    {
+                        println!(
+                            "[ECOM] PERDIDO  {}: {}",
+                            order.id,
+                            describe(&order.outstanding())
+                        );
+                        // se cancela en la tienda para que no lo entregue si responde tarde
+                        order.cancelling_shops.push(msg.shop.zone_id);
+                        let timed_out = order.outcomes.timed_out(msg.shop.zone_id);
+                        let order = order.clone();
+                        ecom.journal(JournalEntry::Cancelling {
+                            sequence: order.id.sequence,
+                            zone_id: msg.shop.zone_id,
+                        });
+                        if timed_out {
+                            ecom.shop_outcome(msg.shop.zone_id, false);
+                        }
+                        ecom.send_to_shop(
+                            msg.shop.zone_id,
+                            WireMessage::Cancel {
+                                id: order.id.clone(),
+                            },
+                            ctx,
+                        );
+                        ctx.address().do_send(ProcessOrder(order));
                     } // caso contrario, sigue pendiente pero ya fue enviada a otra tienda
                 }),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::Read, sync::Arc, thread};
+
+    use actix::Actor;
+    use actix_codec::Decoder;
+    use bytes::BytesMut;
+    use rand::{rngs::StdRng, SeedableRng};
+    use tokio::{
+        io::split,
+        net::TcpStream,
+        sync::{mpsc, Mutex},
+    };
+
+    use super::*;
+    use crate::{
+        constants::ECOM_MAX_WAITING_MILLIS,
+        ecom::{circuit_breaker::CircuitBreaker, heartbeat::Liveness},
+        order_id::OrderId,
+        order_item::OrderItem,
+        protocol::codec::WireCodec,
+    };
+
+    /// Shop in the given zone that never answers. Returns it along with the messages the
+    /// ecom sends it, leaving out the pings
+    fn silent_shop(zone_id: i32) -> (ConnectedShop, mpsc::UnboundedReceiver<WireMessage>) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let listener = std::net::TcpListener::bind("localhost:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut codec = WireCodec::default();
+            let mut buffer = BytesMut::new();
+            let mut bytes = [0u8; 1024];
+            while let Ok(read) = stream.read(&mut bytes) {
+                if read == 0 {
+                    break;
+                }
+                buffer.extend_from_slice(&bytes[..read]);
+                while let Ok(Some(message)) = codec.decode(&mut buffer) {
+                    if message != WireMessage::Ping {
+                        let _ = tx.send(message);
+                    }
+                }
+            }
+        });
+
+        let stream = std::net::TcpStream::connect(address).unwrap();
+        let (_read, write) = split(TcpStream::from_std(stream).unwrap());
+        let shop = ConnectedShop {
+            name: format!("tienda {}", zone_id),
+            zone_id,
+            stream: Arc::new(Mutex::new(write)),
+            catalog_version: None,
+            liveness: Liveness::default(),
+            breaker: CircuitBreaker::default(),
+        };
+        (shop, rx)
+    }
+
+    #[actix_rt::test]
+    async fn test_lost_order_is_cancelled_and_rerouted() {
+        // el reloj queda detenido y salta al siguiente timeout cuando no hay nada que hacer
+        tokio::time::pause();
+        let (near_shop, mut near_messages) = silent_shop(1);
+        let (far_shop, mut far_messages) = silent_shop(5);
+        let mut ecom = Ecom::new("ecom");
+        ecom.shops = vec![near_shop, far_shop];
+        ecom.rng = StdRng::seed_from_u64(0);
+        let ecom = ecom.start();
+
+        let start = std::time::Instant::now();
+        let id = OrderId::new("ecom", 1);
+        ecom.do_send(ProcessOrder(EcomOrder::new(
+            id.clone(),
+            vec![OrderItem::new("A", 1)],
+            1,
+        )));
+
+        // la tienda mas cercana no responde, asi que se le cancela y se pide a la siguiente
+        assert!(matches!(
+            near_messages.recv().await,
+            Some(WireMessage::Prepare { .. })
+        ));
+        assert_eq!(
+            near_messages.recv().await,
+            Some(WireMessage::Cancel { id: id.clone() })
         );
+        match far_messages.recv().await {
+            Some(WireMessage::Prepare { id: sent, .. }) => assert_eq!(sent, id),
+            message => panic!("unexpected message {:?}", message),
+        }
+        assert!(start.elapsed() < Duration::from_millis(ECOM_MAX_WAITING_MILLIS));
     }
 }
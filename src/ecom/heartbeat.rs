@@ -0,0 +1,125 @@
+use std::time::Duration;
+
+use actix::{Context, Handler, Message};
+use colored::Colorize;
+
+use crate::{
+    clock::{self, Instant},
+    config::Config,
+    protocol::wire_message::WireMessage,
+    states::ShopStatus,
+};
+
+use super::ecom_actor::Ecom;
+
+/// Liveness of a connected shop, based on the last time a frame was received from it
+#[derive(Debug, Clone)]
+pub struct Liveness {
+    pub status: ShopStatus,
+    pub last_seen: Instant,
+}
+
+impl Default for Liveness {
+    fn default() -> Self {
+        Self {
+            status: ShopStatus::ALIVE,
+            last_seen: clock::now(),
+        }
+    }
+}
+
+impl Liveness {
+    /// Registers a frame received from the shop. Returns true if the shop was not ALIVE
+    pub fn seen(&mut self, now: Instant) -> bool {
+        self.last_seen = now;
+        let recovered = self.status != ShopStatus::ALIVE;
+        self.status = ShopStatus::ALIVE;
+        recovered
+    }
+
+    /// Updates the status with the time passed since the last frame, using the heartbeat
+    /// times of the configuration. Returns the new status if it changed
+    pub fn check(&mut self, now: Instant, config: &Config) -> Option<ShopStatus> {
+        let silence = now.saturating_duration_since(self.last_seen);
+        let status = if silence >= Duration::from_millis(config.heartbeat_down_millis) {
+            ShopStatus::DOWN
+        } else if silence >= Duration::from_millis(config.heartbeat_suspect_millis) {
+            ShopStatus::SUSPECT
+        } else {
+            ShopStatus::ALIVE
+        };
+
+        if status == self.status {
+            return None;
+        }
+        self.status = status.clone();
+        Some(status)
+    }
+}
+
+#[derive(Debug, Message)]
+#[rtype(result = "()")]
+pub struct Heartbeat;
+
+impl Handler<Heartbeat> for Ecom {
+    type Result = ();
+
+    /// Updates the liveness of every connected shop and sends each one a PING, which the
+    /// shop answers with a PONG
+    fn handle(&mut self, _msg: Heartbeat, ctx: &mut Context<Self>) -> Self::Result {
+        let now = clock::now();
+        for shop in self.shops.iter_mut() {
+            if let Some(status) = shop.liveness.check(now, &self.config) {
+                println!(
+                    "{} Tienda {} {}",
+                    "[ECOM]".purple(),
+                    shop.zone_id,
+                    status.string_to_print()
+                );
+            }
+        }
+
+        let zones: Vec<i32> = self.shops.iter().map(|shop| shop.zone_id).collect();
+        for zone_id in zones {
+            self.send_to_shop(zone_id, WireMessage::Ping, ctx);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_liveness_goes_suspect_down_and_recovers() {
+        let config = Config::default();
+        let (suspect, down) = (
+            config.heartbeat_suspect_millis,
+            config.heartbeat_down_millis,
+        );
+        let start = clock::now();
+        let mut liveness = Liveness {
+            status: ShopStatus::ALIVE,
+            last_seen: start,
+        };
+
+        assert_eq!(liveness.check(start, &config), None);
+        assert_eq!(
+            liveness.check(start + Duration::from_millis(suspect), &config),
+            Some(ShopStatus::SUSPECT)
+        );
+        assert_eq!(
+            liveness.check(start + Duration::from_millis(down), &config),
+            Some(ShopStatus::DOWN)
+        );
+        assert_eq!(
+            liveness.check(start + Duration::from_millis(down + 1), &config),
+            None
+        );
+
+        let later = start + Duration::from_millis(down * 2);
+        assert!(liveness.seen(later));
+        assert_eq!(liveness.status, ShopStatus::ALIVE);
+        assert!(!liveness.seen(later));
+    }
+}
@@ -0,0 +1,347 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs::{self, File, OpenOptions},
+    io::{BufRead, BufReader, Read, Write},
+    path::Path,
+};
+
+use crate::{
+    error::FileError, order_id::OrderId, order_item::OrderItem, states::OnlinePurchaseState,
+};
+
+use super::ecom_actor::{EcomOrder, OrderStep};
+
+/// Event of an order, written to the journal before the ecom acts on it.
+/// Orders are identified by their sequence, the journal belongs to a single ecom.
+#[derive(Debug, Clone, PartialEq)]
+pub enum JournalEntry {
+    Created {
+        sequence: u32,
+        zone_id: i32,
+        items: Vec<OrderItem>,
+    },
+    /// The outstanding items of the order were requested to the shop in the zone
+    Forwarded {
+        sequence: u32,
+        zone_id: i32,
+        allow_partial: bool,
+    },
+    /// The shop in the zone was asked to cancel the order
+    Cancelling { sequence: u32, zone_id: i32 },
+    /// The shop in the zone answered a state for the order
+    State {
+        sequence: u32,
+        zone_id: i32,
+        state: OnlinePurchaseState,
+        items: Vec<OrderItem>,
+    },
+    /// The order was delivered or cancelled, and is no longer pending
+    Finished { sequence: u32 },
+}
+
+impl JournalEntry {
+    /// Line written to the journal for the entry, with its fields separated by commas
+    /// and its items as `<product>,<quantity>` pairs at the end
+    pub fn to_line(&self) -> String {
+        let (fields, items) = match self {
+            JournalEntry::Created {
+                sequence,
+                zone_id,
+                items,
+            } => (format!("creado,{},{}", sequence, zone_id), items.as_slice()),
+            JournalEntry::Forwarded {
+                sequence,
+                zone_id,
+                allow_partial,
+            } => (
+                format!("enviado,{},{},{}", sequence, zone_id, *allow_partial as u8),
+                &[][..],
+            ),
+            JournalEntry::Cancelling { sequence, zone_id } => {
+                (format!("cancelando,{},{}", sequence, zone_id), &[][..])
+            }
+            JournalEntry::State {
+                sequence,
+                zone_id,
+                state,
+                items,
+            } => (
+                format!("estado,{},{},{}", sequence, zone_id, state.to_int()),
+                items.as_slice(),
+            ),
+            JournalEntry::Finished { sequence } => (format!("terminado,{}", sequence), &[][..]),
+        };
+
+        items.iter().fold(fields, |line, item| {
+            format!("{},{},{}", line, item.product, item.quantity)
+        })
+    }
+
+    /// Parses a line written by `to_line`
+    pub fn from_line(line: &str) -> Result<Self, FileError> {
+        let fields: Vec<&str> = line.split(',').collect();
+        let number = |index: usize| -> Result<i64, FileError> {
+            fields
+                .get(index)
+                .and_then(|field| field.parse().ok())
+                .ok_or(FileError::WrongFormat)
+        };
+        let items = |from: usize| -> Result<Vec<OrderItem>, FileError> {
+            let rest = fields.get(from..).unwrap_or(&[]);
+            if rest.len() % 2 != 0 {
+                return Err(FileError::WrongFormat);
+            }
+            rest.chunks(2)
+                .map(|pair| {
+                    let quantity = pair[1].parse().map_err(|_| FileError::WrongFormat)?;
+                    Ok(OrderItem::new(pair[0], quantity))
+                })
+                .collect()
+        };
+        let sequence = number(1)? as u32;
+
+        let entry = match fields[0] {
+            "creado" => JournalEntry::Created {
+                sequence,
+                zone_id: number(2)? as i32,
+                items: items(3)?,
+            },
+            "enviado" if fields.len() == 4 => JournalEntry::Forwarded {
+                sequence,
+                zone_id: number(2)? as i32,
+                allow_partial: number(3)? != 0,
+            },
+            "cancelando" if fields.len() == 3 => JournalEntry::Cancelling {
+                sequence,
+                zone_id: number(2)? as i32,
+            },
+            "estado" => JournalEntry::State {
+                sequence,
+                zone_id: number(2)? as i32,
+                state: OnlinePurchaseState::from_int(number(3)? as u8)
+                    .ok_or(FileError::WrongFormat)?,
+                items: items(4)?,
+            },
+            "terminado" if fields.len() == 2 => JournalEntry::Finished { sequence },
+            _ => return Err(FileError::WrongFormat),
+        };
+        Ok(entry)
+    }
+}
+
+/// Orders rebuilt from a journal
+#[derive(Debug, Default)]
+pub struct JournalReplay {
+    /// Orders not finished yet
+    pub pending_orders: HashMap<OrderId, EcomOrder>,
+    /// Every order created, finished or not
+    pub created: HashSet<OrderId>,
+}
+
+/// Append-only log of the events of the orders of an ecom, to rebuild its pending
+/// orders if the ecom dies
+#[derive(Debug)]
+pub struct Journal {
+    file: File,
+}
+
+impl Journal {
+    /// Path of the journal of the given ecom in the given directory
+    pub fn path_for(dir: &str, ecom: &str) -> String {
+        format!("{}/{}.txt", dir, ecom)
+    }
+
+    /// Creates an empty journal in the given path, replacing the previous one if any
+    pub fn create(path: &str) -> Result<Self, FileError> {
+        if let Some(dir) = Path::new(path).parent() {
+            fs::create_dir_all(dir).map_err(|_| FileError::CannotWrite)?;
+        }
+        let file = File::create(path).map_err(|_| FileError::CannotWrite)?;
+        Ok(Self { file })
+    }
+
+    /// Opens the journal in the given path to keep appending entries to it
+    pub fn open(path: &str) -> Result<Self, FileError> {
+        if let Some(dir) = Path::new(path).parent() {
+            fs::create_dir_all(dir).map_err(|_| FileError::CannotWrite)?;
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|_| FileError::CannotWrite)?;
+        Ok(Self { file })
+    }
+
+    /// Writes the entry at the end of the journal, and waits for it to reach the disk
+    pub fn append(&mut self, entry: &JournalEntry) -> Result<(), FileError> {
+        writeln!(self.file, "{}", entry.to_line()).map_err(|_| FileError::CannotWrite)?;
+        self.file.sync_data().map_err(|_| FileError::CannotWrite)
+    }
+
+    /// Rebuilds the orders of the given ecom from the journal in the given path.
+    /// Without a journal, there are no orders.
+    pub fn replay(path: &str, ecom: &str) -> Result<JournalReplay, FileError> {
+        match File::open(path) {
+            Ok(file) => Self::replay_reader(file, ecom),
+            Err(_) => Ok(JournalReplay::default()),
+        }
+    }
+
+    /// Rebuilds the orders of the given ecom from the journal in the given reader.
+    /// A wrong last line is ignored, as the ecom may have died while writing it.
+    fn replay_reader<T: Read>(content: T, ecom: &str) -> Result<JournalReplay, FileError> {
+        let lines = BufReader::new(content)
+            .lines()
+            .collect::<Result<Vec<String>, _>>()
+            .map_err(|_| FileError::WrongFormat)?;
+        let mut replay = JournalReplay::default();
+
+        for (index, line) in lines.iter().enumerate() {
+            let entry = match JournalEntry::from_line(line) {
+                Ok(entry) => entry,
+                Err(_) if index == lines.len() - 1 => break,
+                Err(error) => return Err(error),
+            };
+            replay.apply(entry, ecom);
+        }
+        Ok(replay)
+    }
+}
+
+impl JournalReplay {
+    /// Applies the entry to the orders, the same way the ecom did when it was written
+    fn apply(&mut self, entry: JournalEntry, ecom: &str) {
+        match entry {
+            JournalEntry::Created {
+                sequence,
+                zone_id,
+                items,
+            } => {
+                let id = OrderId::new(ecom, sequence);
+                self.created.insert(id.clone());
+                self.pending_orders
+                    .insert(id.clone(), EcomOrder::new(id, items, zone_id));
+            }
+            JournalEntry::Forwarded {
+                sequence,
+                zone_id,
+                allow_partial,
+            } => {
+                if let Some(order) = self.order(ecom, sequence) {
+                    if allow_partial && !order.allow_partial {
+                        // se empezo a buscar entre varias tiendas
+                        order.allow_partial = true;
+                        order.shops_requested.clear();
+                    }
+                    order.request_shop(zone_id);
+                }
+            }
+            JournalEntry::Cancelling { sequence, zone_id } => {
+                if let Some(order) = self.order(ecom, sequence) {
+                    order.cancelling_shops.push(zone_id);
+                }
+            }
+            JournalEntry::State {
+                sequence,
+                zone_id,
+                state,
+                items,
+            } => {
+                let step = match self.order(ecom, sequence) {
+                    Some(order) => order.apply_state(zone_id, state, items),
+                    None => return,
+                };
+                match step {
+                    OrderStep::Commit => {
+                        if let Some(order) = self.order(ecom, sequence) {
+                            order.commit_reservations();
+                        }
+                    }
+                    OrderStep::Done => {
+                        self.pending_orders.remove(&OrderId::new(ecom, sequence));
+                    }
+                    OrderStep::Wait | OrderStep::Process => {}
+                }
+            }
+            JournalEntry::Finished { sequence } => {
+                self.pending_orders.remove(&OrderId::new(ecom, sequence));
+            }
+        }
+    }
+
+    fn order(&mut self, ecom: &str, sequence: u32) -> Option<&mut EcomOrder> {
+        self.pending_orders.get_mut(&OrderId::new(ecom, sequence))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_entry_lines_round_trip() {
+        let entries = vec![
+            JournalEntry::Created {
+                sequence: 3,
+                zone_id: 2,
+                items: vec![OrderItem::new("A", 5), OrderItem::new("B", 1)],
+            },
+            JournalEntry::Forwarded {
+                sequence: 3,
+                zone_id: 1,
+                allow_partial: true,
+            },
+            JournalEntry::Cancelling {
+                sequence: 3,
+                zone_id: 1,
+            },
+            JournalEntry::State {
+                sequence: 3,
+                zone_id: 1,
+                state: OnlinePurchaseState::PARTIAL,
+                items: vec![OrderItem::new("A", 2)],
+            },
+            JournalEntry::Finished { sequence: 3 },
+        ];
+
+        for entry in entries {
+            assert_eq!(JournalEntry::from_line(&entry.to_line()).unwrap(), entry);
+        }
+        assert!(JournalEntry::from_line("estado,3,1,9").is_err());
+        assert!(JournalEntry::from_line("creado,3,1,A").is_err());
+    }
+
+    #[test]
+    fn test_replay_rebuilds_pending_orders() {
+        let journal = "creado,0,1,A,5\n\
+                       creado,1,2,A,3,B,2\n\
+                       enviado,0,1,0\n\
+                       enviado,1,2,0\n\
+                       estado,0,1,1,A,5\n\
+                       estado,0,1,3,A,5\n\
+                       estado,1,2,5,A,3\n\
+                       enviado,1,3,1\n\
+                       estado,1,3,1,B,2\n\
+                       estado,1,2,3,A,3\n\
+                       estad";
+
+        let replay = Journal::replay_reader(journal.as_bytes(), "ecom1").unwrap();
+
+        assert_eq!(replay.created.len(), 2);
+        assert_eq!(replay.pending_orders.len(), 1);
+        let order = &replay.pending_orders[&OrderId::new("ecom1", 1)];
+        assert!(order.allow_partial);
+        assert!(order.reservations.is_empty());
+        assert_eq!(order.committed.len(), 1);
+        assert_eq!(order.committed[0].zone_id, 3);
+        assert_eq!(order.delivered, vec![OrderItem::new("A", 3)]);
+        assert_eq!(
+            order.requests.last().unwrap().items,
+            vec![OrderItem::new("B", 2)]
+        );
+
+        let broken = "creado,0,1,A,5\nestad\ncreado,1,1,A,5";
+        assert!(Journal::replay_reader(broken.as_bytes(), "ecom1").is_err());
+    }
+}
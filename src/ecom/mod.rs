@@ -1,7 +1,15 @@
+pub mod circuit_breaker;
 pub mod connect_shops;
 pub mod connected_shops;
 pub mod connection_handling;
 pub mod ecom_actor;
 pub mod foward_order;
+pub mod heartbeat;
+pub mod journal;
 pub mod process_ecom_orders;
 pub mod process_order;
+#[cfg(feature = "harness")]
+pub mod read_orders;
+pub mod reconnect_shop;
+pub mod resume_orders;
+pub mod routing;
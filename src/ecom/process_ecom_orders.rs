@@ -1,9 +1,9 @@
 use std::time::Duration;
 
 use actix::{dev::ContextFutureSpawner, fut::wrap_future, AsyncContext, Handler, Message};
-use rand::{thread_rng, Rng};
+use rand::Rng;
 
-use crate::constants::ECOM_PROCESING_MILLIS;
+use crate::clock;
 
 use super::{
     ecom_actor::{Ecom, EcomOrder},
@@ -25,12 +25,12 @@ impl Handler<ProcessEcomOrders> for Ecom {
             None => return,
         };
         let address = ctx.address().clone();
+        let millis = self
+            .rng
+            .gen_range(self.config.ecom_procesing_millis.clone());
 
         wrap_future::<_, Self>(async move {
-            tokio::time::sleep(Duration::from_millis(
-                thread_rng().gen_range(ECOM_PROCESING_MILLIS),
-            ))
-            .await;
+            clock::sleep(Duration::from_millis(millis)).await;
             address.do_send(ProcessOrder(next_order));
 
             msg.0.remove(0);
@@ -1,9 +1,12 @@
 use actix::{AsyncContext, Handler, Message};
 use colored::Colorize;
 
+use crate::order_item::describe;
+
 use super::{
     ecom_actor::{Ecom, EcomOrder},
     foward_order::FowardOrder,
+    journal::JournalEntry,
 };
 
 #[derive(Debug, Message)]
@@ -13,48 +16,84 @@ pub struct ProcessOrder(pub EcomOrder);
 impl Handler<ProcessOrder> for Ecom {
     type Result = ();
 
-    /// Processes the given order, sending it to the shop which zone id is the closest to the
-    /// order's zone id and has not been requested yet.
+    /// Processes the given order, sending its outstanding items to the shop chosen by the
+    /// routing strategy among the ones that have not been requested yet.
+    /// If no single shop could take the whole order, the shops are requested again allowing
+    /// PARTIAL reservations. If there are still no more shops, its prepared reservations get
+    /// aborted, and the order finishes once the parts already committed finish too, as
+    /// partial if some of them got delivered.
     fn handle(&mut self, msg: ProcessOrder, ctx: &mut Self::Context) -> Self::Result {
-        let order = match self.pending_orders.get(&msg.0.id) {
-            Some(order) => order,
+        let mut order = match self.pending_orders.get(&msg.0.id) {
+            Some(order) => order.clone(),
             None => {
-                self.pending_orders.insert(msg.0.id, msg.0.clone());
-                self.pending_orders
-                    .get(&msg.0.id)
-                    .expect("ESTO NO DEBERIA OCURRIR")
+                self.journal(JournalEntry::Created {
+                    sequence: msg.0.id.sequence,
+                    zone_id: msg.0.zone_id,
+                    items: msg.0.items.clone(),
+                });
+                self.pending_orders.insert(msg.0.id.clone(), msg.0.clone());
+                msg.0.clone()
             }
         };
+        if order.exhausted || order.outstanding().is_empty() {
+            return;
+        }
+
+        let mut shop_to_send = self.find_delivery_shop(&order);
 
-        let shop_to_send = self.find_delivery_shop(order);
+        if shop_to_send.is_none() && !order.allow_partial {
+            println!(
+                "{} Pedido {} ({}): buscando entre varias tiendas",
+                "[ECOM]".purple(),
+                order.id,
+                describe(&order.outstanding())
+            );
+            if let Some(pending) = self.pending_orders.get_mut(&msg.0.id) {
+                pending.allow_partial = true;
+                pending.shops_requested.clear();
+                order = pending.clone();
+            }
+            shop_to_send = self.find_delivery_shop(&order);
+        }
 
         let shop = match shop_to_send {
             Some(shop) => shop,
             None => {
-                println!(
-                    "{} Pedido {}: {:<2}x {} (No hay mas tiendas)",
-                    "[ECOM]".purple(),
-                    "CANCELADO".on_red(),
-                    order.quantity,
-                    order.product_id
-                );
-
-                self.pending_orders.remove_entry(&msg.0.id);
+                self.abort_order(&msg.0.id, ctx);
+                let order = match self.pending_orders.get_mut(&msg.0.id) {
+                    Some(order) => order,
+                    None => return,
+                };
+                order.exhausted = true;
+                if order.committed.is_empty() {
+                    self.give_up_order(&msg.0.id);
+                } else {
+                    println!(
+                        "{} Pedido {} ({}): no hay mas tiendas, esperando las entregas en curso",
+                        "[ECOM]".purple(),
+                        order.id,
+                        describe(&order.outstanding())
+                    );
+                }
                 return;
             }
         };
 
+        self.journal(JournalEntry::Forwarded {
+            sequence: order.id.sequence,
+            zone_id: shop.zone_id,
+            allow_partial: order.allow_partial,
+        });
         let order = match self.pending_orders.get_mut(&msg.0.id) {
             Some(order) => {
-                order.shops_requested.push(shop.zone_id);
+                order.request_shop(shop.zone_id);
                 order
             }
             None => return, // ESTO NO DEBERIA OCURRIR
         };
+        let order = order.clone();
+        self.shop_requested(shop.zone_id);
 
-        ctx.address().do_send(FowardOrder {
-            order: order.clone(),
-            shop,
-        });
+        ctx.address().do_send(FowardOrder { order, shop });
     }
 }
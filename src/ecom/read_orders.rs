@@ -0,0 +1,28 @@
+use actix::{Context, Handler, Message, MessageResult};
+
+use super::ecom_actor::{Ecom, EcomOrder};
+
+/// Returns the orders of the ecom that are still pending and the ones already delivered or
+/// cancelled, sorted by id
+#[derive(Debug, Message)]
+#[rtype(result = "EcomOrders")]
+pub struct ReadOrders;
+
+#[derive(Debug, Clone)]
+pub struct EcomOrders {
+    pub pending: Vec<EcomOrder>,
+    pub finished: Vec<EcomOrder>,
+}
+
+impl Handler<ReadOrders> for Ecom {
+    type Result = MessageResult<ReadOrders>;
+
+    fn handle(&mut self, _msg: ReadOrders, _ctx: &mut Context<Self>) -> Self::Result {
+        let mut pending: Vec<EcomOrder> = self.pending_orders.values().cloned().collect();
+        pending.sort_by(|a, b| a.id.cmp(&b.id));
+        let mut finished = self.finished_orders.clone();
+        finished.sort_by(|a, b| a.id.cmp(&b.id));
+
+        MessageResult(EcomOrders { pending, finished })
+    }
+}
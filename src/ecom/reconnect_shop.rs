@@ -0,0 +1,123 @@
+use std::time::Duration;
+
+use actix::{AsyncContext, Context, Handler, Message};
+use colored::Colorize;
+use rand::Rng;
+
+use crate::{config::Config, order_id::OrderId, protocol::wire_message::WireMessage};
+
+use super::ecom_actor::Ecom;
+
+#[derive(Debug, Message)]
+#[rtype(result = "()")]
+pub struct ReconnectShop {
+    pub zone_id: i32,
+    /// Attempts already made to reconnect the shop
+    pub attempt: u32,
+    /// Orders that were waiting for the shop when the connection was lost
+    pub in_flight: Vec<OrderId>,
+}
+
+/// Time to wait before the given reconnection attempt. The backoff doubles with each attempt
+/// up to `reconnect_max_millis`, and the delay is a random time between half the backoff and
+/// the whole backoff
+pub fn reconnect_delay<R: Rng>(attempt: u32, config: &Config, rng: &mut R) -> Duration {
+    let backoff = config
+        .reconnect_base_millis
+        .saturating_mul(1 << attempt.min(16))
+        .min(config.reconnect_max_millis);
+    Duration::from_millis(backoff / 2 + rng.gen_range(0..=backoff / 2))
+}
+
+impl Handler<ReconnectShop> for Ecom {
+    type Result = ();
+
+    /// Tries to reconnect the shop in the given zone. If it fails, the next attempt gets
+    /// scheduled. Once connected, the orders that were in flight are sent again, along with
+    /// the cancellations the shop could not receive.
+    fn handle(&mut self, msg: ReconnectShop, ctx: &mut Context<Self>) -> Self::Result {
+        let already_connected = self.shops.iter().any(|shop| shop.zone_id == msg.zone_id);
+
+        if !already_connected {
+            println!(
+                "{} Reconectando tienda {} (intento {})...",
+                "[ECOM]".purple(),
+                msg.zone_id,
+                msg.attempt + 1
+            );
+
+            let connected = match self.shop_address(msg.zone_id) {
+                Ok(Some((name, zone_id, address))) => {
+                    self.connect_shop(ctx, name, zone_id, address).is_ok()
+                }
+                _ => false,
+            };
+
+            if !connected {
+                let attempt = msg.attempt + 1;
+                ctx.notify_later(
+                    ReconnectShop {
+                        zone_id: msg.zone_id,
+                        attempt,
+                        in_flight: msg.in_flight,
+                    },
+                    reconnect_delay(attempt, &self.config, &mut self.rng),
+                );
+                return;
+            }
+            println!(
+                "{}",
+                format!("[ECOM] Tienda {} reconectada", msg.zone_id).purple()
+            );
+            // los pedidos se reenvian luego del Hello a la tienda
+            ctx.notify(msg);
+            return;
+        }
+
+        for id in msg.in_flight {
+            let order = match self.pending_orders.get(&id) {
+                Some(order) if order.awaits(msg.zone_id) => order,
+                _ => continue, // ya se resolvio o se envio a otra tienda
+            };
+            println!(
+                "{} Reenviando pedido {} a la tienda {}",
+                "[ECOM]".purple(),
+                id,
+                msg.zone_id
+            );
+            self.send_to_shop(msg.zone_id, order.to_message(), ctx);
+        }
+
+        let cancelling: Vec<OrderId> = self
+            .pending_orders
+            .values()
+            .filter(|order| order.cancelling_shops.contains(&msg.zone_id))
+            .map(|order| order.id.clone())
+            .collect();
+        for id in cancelling {
+            self.send_to_shop(msg.zone_id, WireMessage::Cancel { id }, ctx);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+
+    #[test]
+    fn test_reconnect_delay_grows_until_max() {
+        let config = Config::default();
+        let (base, max) = (config.reconnect_base_millis, config.reconnect_max_millis);
+        let mut rng = StdRng::seed_from_u64(42);
+        for attempt in 0..20 {
+            let backoff = (base << attempt.min(16)).min(max);
+            let delay = reconnect_delay(attempt, &config, &mut rng).as_millis() as u64;
+
+            assert!(delay >= backoff / 2);
+            assert!(delay <= backoff);
+        }
+        assert!(reconnect_delay(30, &config, &mut rng).as_millis() as u64 >= max / 2);
+    }
+}
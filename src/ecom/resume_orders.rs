@@ -0,0 +1,101 @@
+use std::time::Duration;
+
+use actix::{AsyncContext, Context, Handler, Message};
+use colored::Colorize;
+
+use crate::{order_id::OrderId, order_item::describe, protocol::wire_message::WireMessage};
+
+use super::{ecom_actor::Ecom, foward_order::FowardOrder, process_order::ProcessOrder};
+
+/// Resumes the pending orders recovered from the journal, once the shops are connected
+#[derive(Debug, Message)]
+#[rtype(result = "()")]
+pub struct ResumeOrders;
+
+/// Asks the shops delivering the order for its state, until every one of them answered
+/// its delivery
+#[derive(Debug, Message)]
+#[rtype(result = "()")]
+pub struct QueryCommitted(pub OrderId);
+
+impl Handler<ResumeOrders> for Ecom {
+    type Result = ();
+
+    /// For each recovered order:
+    /// - The shop it was waiting for gets the order again, restarting its timeout. The shop
+    ///   answers retransmitted orders without reserving the stock again.
+    /// - The shops asked to cancel it get the cancellation again.
+    /// - The shops delivering it get the commit again, and are asked for its state.
+    /// - If it has items no shop is taking care of, they are requested to another shop.
+    fn handle(&mut self, _msg: ResumeOrders, ctx: &mut Context<Self>) -> Self::Result {
+        let mut orders: Vec<_> = self.pending_orders.values().cloned().collect();
+        orders.sort_by(|a, b| a.id.cmp(&b.id));
+
+        for order in orders {
+            println!(
+                "{} Retomando pedido {}: {}",
+                "[ECOM]".purple(),
+                order.id,
+                describe(&order.items)
+            );
+
+            for zone_id in order.cancelling_shops.iter() {
+                let id = order.id.clone();
+                self.send_to_shop(*zone_id, WireMessage::Cancel { id }, ctx);
+            }
+            for reservation in order.committed.iter() {
+                let id = order.id.clone();
+                self.send_to_shop(reservation.zone_id, WireMessage::Commit { id }, ctx);
+            }
+            if !order.committed.is_empty() {
+                ctx.notify(QueryCommitted(order.id.clone()));
+            }
+
+            let waiting = order
+                .shops_requested
+                .last()
+                .filter(|zone_id| order.awaits(**zone_id))
+                .and_then(|zone_id| self.shops.iter().find(|shop| shop.zone_id == *zone_id));
+            match waiting {
+                Some(shop) => ctx.notify(FowardOrder {
+                    order: order.clone(),
+                    shop: shop.clone(),
+                }),
+                None if !order.outstanding().is_empty() => ctx.notify(ProcessOrder(order)),
+                None => {}
+            }
+        }
+    }
+}
+
+impl Handler<QueryCommitted> for Ecom {
+    type Result = ();
+
+    fn handle(&mut self, msg: QueryCommitted, ctx: &mut Context<Self>) -> Self::Result {
+        let order = match self.pending_orders.get(&msg.0) {
+            Some(order) if !order.committed.is_empty() => order.clone(),
+            _ => return,
+        };
+
+        // la tienda responde el ultimo estado del pedido desde su tabla de pedidos
+        for reservation in order.committed.iter() {
+            let request = order
+                .requests
+                .iter()
+                .find(|request| request.zone_id == reservation.zone_id);
+            if let Some(request) = request {
+                let message = WireMessage::Prepare {
+                    id: order.id.clone(),
+                    items: request.items.clone(),
+                    zone_id: order.zone_id,
+                    allow_partial: request.allow_partial,
+                };
+                self.send_to_shop(reservation.zone_id, message, ctx);
+            }
+        }
+        ctx.notify_later(
+            msg,
+            Duration::from_millis(self.config.ecom_max_waiting_millis),
+        );
+    }
+}
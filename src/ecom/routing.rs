@@ -0,0 +1,219 @@
+use std::{collections::HashMap, fmt::Debug};
+
+use rand::{rngs::StdRng, Rng};
+
+use crate::{order_id::OrderId, zones::ZoneMap};
+
+use super::{connected_shops::ConnectedShop, ecom_actor::EcomOrder};
+
+/// Information available to a routing strategy to choose the shop for an order
+pub struct RoutingContext<'a> {
+    pub order: &'a EcomOrder,
+    /// Connected shops that were not requested yet for the order
+    pub candidates: Vec<ConnectedShop>,
+    pub pending_orders: &'a HashMap<OrderId, EcomOrder>,
+    pub zones: &'a ZoneMap,
+    /// Random number generator of the ecom
+    pub rng: &'a mut StdRng,
+}
+
+impl RoutingContext<'_> {
+    /// Shortest path distance between the zone of the shop and the zone of the order.
+    /// Unreachable shops are as far as possible.
+    pub fn distance(&self, shop: &ConnectedShop) -> u32 {
+        self.zones
+            .distance(shop.zone_id, self.order.zone_id)
+            .unwrap_or(u32::MAX)
+    }
+
+    /// Amount of pending orders whose last request was sent to the given shop
+    pub fn load(&self, shop: &ConnectedShop) -> usize {
+        self.pending_orders
+            .values()
+            .filter(|order| order.shops_requested.last() == Some(&shop.zone_id))
+            .count()
+    }
+}
+
+/// Policy used by the ecom to choose which shop should deliver an order
+pub trait RoutingStrategy: Debug {
+    /// Returns the shop that should receive the order, or None if there are no candidates
+    fn select_shop(&mut self, context: RoutingContext) -> Option<ConnectedShop>;
+}
+
+/// Chooses the closest shop to the order's zone
+#[derive(Debug, Default)]
+pub struct NearestRouting;
+
+impl RoutingStrategy for NearestRouting {
+    fn select_shop(&mut self, context: RoutingContext) -> Option<ConnectedShop> {
+        context
+            .candidates
+            .iter()
+            .min_by_key(|shop| context.distance(shop))
+            .cloned()
+    }
+}
+
+/// Chooses the shops in turns, ignoring the zones
+#[derive(Debug, Default)]
+pub struct RoundRobinRouting {
+    next: usize,
+}
+
+impl RoutingStrategy for RoundRobinRouting {
+    fn select_shop(&mut self, mut context: RoutingContext) -> Option<ConnectedShop> {
+        if context.candidates.is_empty() {
+            return None;
+        }
+        context.candidates.sort_by_key(|shop| shop.zone_id);
+        let shop = context.candidates[self.next % context.candidates.len()].clone();
+        self.next = self.next.wrapping_add(1);
+        Some(shop)
+    }
+}
+
+/// Chooses the shop with less pending orders, breaking ties by distance
+#[derive(Debug, Default)]
+pub struct LeastLoadedRouting;
+
+impl RoutingStrategy for LeastLoadedRouting {
+    fn select_shop(&mut self, context: RoutingContext) -> Option<ConnectedShop> {
+        context
+            .candidates
+            .iter()
+            .min_by_key(|shop| (context.load(shop), context.distance(shop)))
+            .cloned()
+    }
+}
+
+/// Chooses a random shop, where closer shops are more likely to be chosen
+#[derive(Debug, Default)]
+pub struct WeightedRandomRouting;
+
+impl RoutingStrategy for WeightedRandomRouting {
+    fn select_shop(&mut self, context: RoutingContext) -> Option<ConnectedShop> {
+        let weights: Vec<f64> = context
+            .candidates
+            .iter()
+            .map(|shop| 1.0 / (1.0 + context.distance(shop) as f64))
+            .collect();
+        let total: f64 = weights.iter().sum();
+        if total <= 0.0 {
+            return None;
+        }
+
+        let mut target = context.rng.gen_range(0.0..total);
+        for (shop, weight) in context.candidates.iter().zip(weights) {
+            if target < weight {
+                return Some(shop.clone());
+            }
+            target -= weight;
+        }
+        context.candidates.last().cloned()
+    }
+}
+
+/// Returns the strategy with the given name, as used in the command line
+pub fn routing_from_name(name: &str) -> Option<Box<dyn RoutingStrategy>> {
+    match name {
+        "nearest" => Some(Box::new(NearestRouting)),
+        "round-robin" => Some(Box::<RoundRobinRouting>::default()),
+        "least-loaded" => Some(Box::new(LeastLoadedRouting)),
+        "weighted-random" => Some(Box::new(WeightedRandomRouting)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, thread};
+
+    use rand::SeedableRng;
+    use tokio::{io::split, net::TcpStream, sync::Mutex};
+
+    use super::*;
+    use crate::ecom::{circuit_breaker::CircuitBreaker, heartbeat::Liveness};
+    use crate::order_item::OrderItem;
+
+    fn connected_shops(zones: &[i32]) -> Vec<ConnectedShop> {
+        let listener = std::net::TcpListener::bind("localhost:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            listener.accept().unwrap();
+        });
+        let stream = std::net::TcpStream::connect(address).unwrap();
+        let (_read, write) = split(TcpStream::from_std(stream).unwrap());
+        let write = Arc::new(Mutex::new(write));
+
+        zones
+            .iter()
+            .map(|zone_id| ConnectedShop {
+                name: format!("tienda {}", zone_id),
+                zone_id: *zone_id,
+                stream: write.clone(),
+                catalog_version: None,
+                liveness: Liveness::default(),
+                breaker: CircuitBreaker::default(),
+            })
+            .collect()
+    }
+
+    fn order(sequence: u32, zone_id: i32, shops_requested: Vec<i32>) -> EcomOrder {
+        let mut order = EcomOrder::new(
+            OrderId::new("ecom", sequence),
+            vec![OrderItem::new("manzana", 1)],
+            zone_id,
+        );
+        order.shops_requested = shops_requested;
+        order
+    }
+
+    #[actix_rt::test]
+    async fn test_round_robin_routing() {
+        let shops = connected_shops(&[5, 1, 11]);
+        let pending_orders = HashMap::new();
+        let order = order(1, 1, vec![]);
+        let mut routing = RoundRobinRouting::default();
+
+        let zones: Vec<i32> = (0..4)
+            .map(|_| {
+                routing
+                    .select_shop(RoutingContext {
+                        order: &order,
+                        candidates: shops.clone(),
+                        pending_orders: &pending_orders,
+                        zones: &ZoneMap::default(),
+                        rng: &mut StdRng::from_entropy(),
+                    })
+                    .unwrap()
+                    .zone_id
+            })
+            .collect();
+
+        assert_eq!(zones, vec![1, 5, 11, 1]);
+    }
+
+    #[actix_rt::test]
+    async fn test_least_loaded_routing() {
+        let shops = connected_shops(&[1, 5]);
+        let mut pending_orders = HashMap::new();
+        for sequence in 0..2 {
+            let busy = order(sequence, 1, vec![1]);
+            pending_orders.insert(busy.id.clone(), busy);
+        }
+        let order = order(3, 1, vec![]);
+
+        let shop = LeastLoadedRouting
+            .select_shop(RoutingContext {
+                order: &order,
+                candidates: shops,
+                pending_orders: &pending_orders,
+                zones: &ZoneMap::default(),
+                rng: &mut StdRng::from_entropy(),
+            })
+            .unwrap();
+
+        assert_eq!(shop.zone_id, 5);
+    }
+}
@@ -4,10 +4,40 @@ pub enum PurchaseError {
     NotDelivered,
 }
 
+#[derive(Debug, PartialEq)]
+pub enum StockError {
+    NotEnoughStock,
+    NotEnoughReserved,
+    TooMuchStock,
+    /// The product is not in the stock
+    UnknownProduct,
+    /// The change could not be written to the inventory log, so it was not applied
+    NotLogged,
+}
+
 #[derive(Debug)]
 pub enum FileError {
     NotFound,
     WrongFormat,
+    CannotWrite,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ZoneError {
+    /// The zone is not in the zone map
+    MissingZone(i32),
+    /// There is no path from the shop zone to the order zone
+    Unreachable(i32, i32),
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    NotFound,
+    WrongFormat(usize),
+    UnknownKey(String),
+    WrongValue(String),
+    MissingValue(String),
+    Invalid(String),
 }
 
 #[derive(Debug)]
@@ -17,3 +47,24 @@ pub enum StreamError {
     CannotRead,
     CannotWrite,
 }
+
+#[derive(Debug)]
+pub enum ProxyError {
+    InvalidPlan(String),
+    CannotListen,
+}
+
+#[derive(Debug)]
+pub enum ProtocolError {
+    UnsupportedVersion(u8),
+    UnknownKind(u8),
+    FrameTooLarge(usize),
+    WrongFormat,
+    Io(std::io::ErrorKind),
+}
+
+impl From<std::io::Error> for ProtocolError {
+    fn from(error: std::io::Error) -> Self {
+        ProtocolError::Io(error.kind())
+    }
+}
@@ -1,5 +1,12 @@
+pub mod chaos;
+pub mod clock;
+pub mod config;
 pub mod constants;
 pub mod ecom;
 pub mod error;
+pub mod order_id;
+pub mod order_item;
+pub mod protocol;
 pub mod shop;
 pub mod states;
+pub mod zones;
@@ -0,0 +1,24 @@
+use std::fmt;
+
+/// Globally unique identifier of an online order: the name of the ecom that created it
+/// plus a sequence number unique inside that ecom.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct OrderId {
+    pub ecom: String,
+    pub sequence: u32,
+}
+
+impl OrderId {
+    pub fn new(ecom: &str, sequence: u32) -> Self {
+        Self {
+            ecom: ecom.to_string(),
+            sequence,
+        }
+    }
+}
+
+impl fmt::Display for OrderId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}#{}", self.ecom, self.sequence)
+    }
+}
@@ -0,0 +1,41 @@
+use std::fmt;
+
+/// A line of an online order: a product and the quantity wanted of it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OrderItem {
+    pub product: String,
+    pub quantity: u32,
+}
+
+impl OrderItem {
+    pub fn new(product: &str, quantity: u32) -> Self {
+        Self {
+            product: product.to_string(),
+            quantity,
+        }
+    }
+}
+
+impl fmt::Display for OrderItem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:<2}x {}", self.quantity, self.product)
+    }
+}
+
+/// Returns the total quantity of the given product among the items
+pub fn quantity_of(items: &[OrderItem], product: &str) -> u32 {
+    items
+        .iter()
+        .filter(|item| item.product == product)
+        .map(|item| item.quantity)
+        .sum()
+}
+
+/// Formats the items as a single line, to be printed in the logs
+pub fn describe(items: &[OrderItem]) -> String {
+    items
+        .iter()
+        .map(|item| item.to_string())
+        .collect::<Vec<String>>()
+        .join(", ")
+}
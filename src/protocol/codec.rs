@@ -0,0 +1,256 @@
+use std::sync::Arc;
+
+use actix_codec::{AsyncRead, Decoder, Encoder, Framed, FramedParts};
+use bytes::{Buf, BufMut, BytesMut};
+use tokio::{
+    io::{AsyncWriteExt, WriteHalf},
+    net::TcpStream,
+    sync::Mutex,
+};
+
+use crate::error::{ProtocolError, StreamError};
+
+use super::wire_message::WireMessage;
+
+pub const PROTOCOL_VERSION: u8 = 3;
+
+/// Bytes used by the length prefix of every frame
+pub const LENGTH_BYTES: usize = 4;
+/// Bytes used by the version and kind of every frame
+const HEADER_BYTES: usize = 2;
+pub const MAX_FRAME_BYTES: usize = 64 * 1024;
+
+/// Codec shared by the ecom and the shops. Every frame has the format:
+/// `<length: u32><version: u8><kind: u8><payload>`, where length counts every byte after itself.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct WireCodec {
+    /// Bytes of an oversized frame that still have to be thrown away
+    discarding: usize,
+}
+
+impl Encoder<WireMessage> for WireCodec {
+    type Error = ProtocolError;
+
+    fn encode(&mut self, message: WireMessage, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        let mut payload = BytesMut::new();
+        message.encode_payload(&mut payload)?;
+
+        let length = HEADER_BYTES + payload.len();
+        if length > MAX_FRAME_BYTES {
+            return Err(ProtocolError::FrameTooLarge(length));
+        }
+
+        dst.reserve(LENGTH_BYTES + length);
+        dst.put_u32(length as u32);
+        dst.put_u8(PROTOCOL_VERSION);
+        dst.put_u8(message.kind());
+        dst.extend_from_slice(&payload);
+        Ok(())
+    }
+}
+
+impl Decoder for WireCodec {
+    type Item = WireMessage;
+    type Error = ProtocolError;
+
+    /// Decodes the next frame, if it is complete.
+    /// Frames with an unsupported version or kind are consumed before returning the error,
+    /// so the following frames in the stream can still be read. Oversized frames are discarded as their bytes arrive.
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        if self.discarding > 0 {
+            let discarded = self.discarding.min(src.len());
+            src.advance(discarded);
+            self.discarding -= discarded;
+            if self.discarding > 0 {
+                return Ok(None);
+            }
+        }
+
+        if src.len() < LENGTH_BYTES {
+            return Ok(None);
+        }
+
+        let mut length_bytes = [0u8; LENGTH_BYTES];
+        length_bytes.copy_from_slice(&src[..LENGTH_BYTES]);
+        let length = u32::from_be_bytes(length_bytes) as usize;
+
+        if length > MAX_FRAME_BYTES {
+            src.advance(LENGTH_BYTES);
+            self.discarding = length;
+            return Err(ProtocolError::FrameTooLarge(length));
+        }
+        if src.len() < LENGTH_BYTES + length {
+            src.reserve(LENGTH_BYTES + length - src.len());
+            return Ok(None);
+        }
+
+        src.advance(LENGTH_BYTES);
+        let mut frame = src.split_to(length).freeze();
+        if length < HEADER_BYTES {
+            return Err(ProtocolError::WrongFormat);
+        }
+
+        let version = frame.get_u8();
+        let kind = frame.get_u8();
+        if version != PROTOCOL_VERSION {
+            return Err(ProtocolError::UnsupportedVersion(version));
+        }
+
+        WireMessage::decode_payload(kind, frame).map(Some)
+    }
+
+    /// Decodes the frames left once the connection is closed. A frame cut in the middle by
+    /// the closing is thrown away, so the stream ends instead of failing over and over.
+    fn decode_eof(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        match self.decode(src)? {
+            Some(message) => Ok(Some(message)),
+            None => {
+                src.clear();
+                Ok(None)
+            }
+        }
+    }
+}
+
+/// Wraps the read half of a connection into a stream of decoded messages
+pub fn framed_read<T: AsyncRead>(read: T) -> Framed<T, WireCodec> {
+    Framed::from_parts(FramedParts::new(read, WireCodec::default()))
+}
+
+/// Encodes the given message and writes it to the given stream
+pub async fn send_message(
+    write: &Arc<Mutex<WriteHalf<TcpStream>>>,
+    message: WireMessage,
+) -> Result<(), StreamError> {
+    let mut frame = BytesMut::new();
+    WireCodec::default()
+        .encode(message, &mut frame)
+        .map_err(|_| StreamError::WrongFormat)?;
+
+    let mut write = write.lock().await;
+    write
+        .write_all(&frame)
+        .await
+        .map_err(|_| StreamError::CannotWrite)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{order_id::OrderId, order_item::OrderItem, states::OnlinePurchaseState};
+
+    use super::*;
+
+    #[test]
+    fn test_cart_round_trip_with_comma_in_product() {
+        let message = WireMessage::Prepare {
+            id: OrderId::new("ecom1", 300),
+            items: vec![
+                OrderItem::new("galleta, chocolate", 2),
+                OrderItem::new("manzana", 1),
+            ],
+            zone_id: -1,
+            allow_partial: true,
+        };
+        let mut codec = WireCodec::default();
+        let mut buffer = BytesMut::new();
+        codec.encode(message.clone(), &mut buffer).unwrap();
+
+        let decoded = codec.decode(&mut buffer).unwrap();
+
+        assert_eq!(decoded, Some(message));
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn test_partial_frame_waits_for_more_bytes() {
+        let message = WireMessage::OrderState {
+            id: OrderId::new("ecom1", 1),
+            state: OnlinePurchaseState::DELIVERED,
+            items: vec![OrderItem::new("manzana", 3)],
+        };
+        let mut codec = WireCodec::default();
+        let mut encoded = BytesMut::new();
+        codec.encode(message.clone(), &mut encoded).unwrap();
+
+        let mut buffer = BytesMut::from(&encoded[..3]);
+        assert_eq!(codec.decode(&mut buffer).unwrap(), None);
+
+        buffer.extend_from_slice(&encoded[3..]);
+        assert_eq!(codec.decode(&mut buffer).unwrap(), Some(message));
+    }
+
+    #[test]
+    fn test_truncated_frame_is_dropped_at_eof() {
+        let message = WireMessage::Commit {
+            id: OrderId::new("ecom1", 4),
+        };
+        let mut codec = WireCodec::default();
+        let mut buffer = BytesMut::new();
+        codec.encode(message.clone(), &mut buffer).unwrap();
+        codec.encode(message.clone(), &mut buffer).unwrap();
+        buffer.truncate(buffer.len() - 3);
+
+        assert_eq!(codec.decode_eof(&mut buffer).unwrap(), Some(message));
+        assert_eq!(codec.decode_eof(&mut buffer).unwrap(), None);
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn test_unsupported_version_is_skipped() {
+        let message = WireMessage::OrderState {
+            id: OrderId::new("ecom1", 7),
+            state: OnlinePurchaseState::LOST,
+            items: vec![OrderItem::new("manzana", 3)],
+        };
+        let mut codec = WireCodec::default();
+        let mut buffer = BytesMut::new();
+        codec.encode(message.clone(), &mut buffer).unwrap();
+        buffer[LENGTH_BYTES] = PROTOCOL_VERSION + 1;
+        codec.encode(message.clone(), &mut buffer).unwrap();
+
+        assert!(matches!(
+            codec.decode(&mut buffer),
+            Err(ProtocolError::UnsupportedVersion(_))
+        ));
+        assert_eq!(codec.decode(&mut buffer).unwrap(), Some(message));
+    }
+
+    #[test]
+    fn test_unknown_kind() {
+        let mut codec = WireCodec::default();
+        let mut buffer = BytesMut::new();
+        buffer.put_u32(HEADER_BYTES as u32);
+        buffer.put_u8(PROTOCOL_VERSION);
+        buffer.put_u8(u8::MAX);
+
+        assert!(matches!(
+            codec.decode(&mut buffer),
+            Err(ProtocolError::UnknownKind(u8::MAX))
+        ));
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn test_oversized_frame_is_discarded() {
+        let message = WireMessage::OrderState {
+            id: OrderId::new("ecom1", 2),
+            state: OnlinePurchaseState::REJECTED,
+            items: vec![OrderItem::new("manzana", 3)],
+        };
+        let mut codec = WireCodec::default();
+        let mut buffer = BytesMut::new();
+        buffer.put_u32((MAX_FRAME_BYTES + 1) as u32);
+
+        assert!(matches!(
+            codec.decode(&mut buffer),
+            Err(ProtocolError::FrameTooLarge(_))
+        ));
+
+        buffer.extend_from_slice(&vec![0u8; MAX_FRAME_BYTES]);
+        assert_eq!(codec.decode(&mut buffer).unwrap(), None);
+
+        buffer.put_u8(0);
+        codec.encode(message.clone(), &mut buffer).unwrap();
+        assert_eq!(codec.decode(&mut buffer).unwrap(), Some(message));
+    }
+}
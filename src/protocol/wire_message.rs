@@ -0,0 +1,251 @@
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+
+use crate::{
+    error::ProtocolError, order_id::OrderId, order_item::OrderItem, states::OnlinePurchaseState,
+};
+
+const PREPARE_KIND: u8 = 1;
+const ORDER_STATE_KIND: u8 = 2;
+const HELLO_KIND: u8 = 3;
+const WELCOME_KIND: u8 = 4;
+const COMMIT_KIND: u8 = 5;
+const ABORT_KIND: u8 = 6;
+const CANCEL_KIND: u8 = 7;
+const PING_KIND: u8 = 8;
+const PONG_KIND: u8 = 9;
+const RESTOCKED_KIND: u8 = 10;
+
+/// Messages exchanged between the ecom and the shop server side.
+#[derive(Debug, Clone, PartialEq)]
+pub enum WireMessage {
+    /// First phase of an online order: asks the shop to reserve and hold the stock of every item
+    Prepare {
+        id: OrderId,
+        items: Vec<OrderItem>,
+        zone_id: i32,
+        allow_partial: bool,
+    },
+    /// State of an online order sent from a shop back to the ecom, with the items it refers to
+    OrderState {
+        id: OrderId,
+        state: OnlinePurchaseState,
+        items: Vec<OrderItem>,
+    },
+    /// First message sent by the ecom after connecting, to identify itself
+    Hello { ecom: String },
+    /// Answer of the shop to a Hello
+    Welcome {
+        shop: String,
+        zone_id: i32,
+        catalog_version: u32,
+    },
+    /// Sent by the ecom once the whole order is reserved, so the shop delivers its reservation
+    Commit { id: OrderId },
+    /// Sent by the ecom when the order cannot be completed, so the shop releases its reservation
+    Abort { id: OrderId },
+    /// Sent by the ecom to retract an order it gave up waiting for, so the shop does not
+    /// deliver it. The shop answers CANCELLED, or TOO_LATE if the delivery already started
+    Cancel { id: OrderId },
+    /// Sent periodically by the ecom to check that the shop is alive
+    Ping,
+    /// Answer of the shop to a Ping
+    Pong,
+    /// Sent by the shop to every identified ecom when a supplier restocks it, with the
+    /// items restocked
+    Restocked { shop: String, items: Vec<OrderItem> },
+}
+
+impl WireMessage {
+    /// Returns the byte that identifies the kind of the message inside a frame
+    pub fn kind(&self) -> u8 {
+        match self {
+            WireMessage::Prepare { .. } => PREPARE_KIND,
+            WireMessage::OrderState { .. } => ORDER_STATE_KIND,
+            WireMessage::Hello { .. } => HELLO_KIND,
+            WireMessage::Welcome { .. } => WELCOME_KIND,
+            WireMessage::Commit { .. } => COMMIT_KIND,
+            WireMessage::Abort { .. } => ABORT_KIND,
+            WireMessage::Cancel { .. } => CANCEL_KIND,
+            WireMessage::Ping => PING_KIND,
+            WireMessage::Pong => PONG_KIND,
+            WireMessage::Restocked { .. } => RESTOCKED_KIND,
+        }
+    }
+
+    /// Writes the fields of the message into the given buffer
+    pub fn encode_payload(&self, dst: &mut BytesMut) -> Result<(), ProtocolError> {
+        match self {
+            WireMessage::Prepare {
+                id,
+                items,
+                zone_id,
+                allow_partial,
+            } => {
+                put_order_id(dst, id)?;
+                put_items(dst, items)?;
+                dst.put_i32(*zone_id);
+                dst.put_u8(u8::from(*allow_partial));
+            }
+            WireMessage::OrderState { id, state, items } => {
+                put_order_id(dst, id)?;
+                dst.put_u8(state.to_int());
+                put_items(dst, items)?;
+            }
+            WireMessage::Hello { ecom } => {
+                put_string(dst, ecom)?;
+            }
+            WireMessage::Welcome {
+                shop,
+                zone_id,
+                catalog_version,
+            } => {
+                put_string(dst, shop)?;
+                dst.put_i32(*zone_id);
+                dst.put_u32(*catalog_version);
+            }
+            WireMessage::Commit { id } | WireMessage::Abort { id } | WireMessage::Cancel { id } => {
+                put_order_id(dst, id)?;
+            }
+            WireMessage::Ping | WireMessage::Pong => {}
+            WireMessage::Restocked { shop, items } => {
+                put_string(dst, shop)?;
+                put_items(dst, items)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads a message of the given kind from the payload of a frame.
+    /// The whole payload must be consumed, otherwise the frame is considered malformed.
+    pub fn decode_payload(kind: u8, mut payload: Bytes) -> Result<Self, ProtocolError> {
+        let message = match kind {
+            PREPARE_KIND => WireMessage::Prepare {
+                id: get_order_id(&mut payload)?,
+                items: get_items(&mut payload)?,
+                zone_id: get_i32(&mut payload)?,
+                allow_partial: get_bool(&mut payload)?,
+            },
+            ORDER_STATE_KIND => WireMessage::OrderState {
+                id: get_order_id(&mut payload)?,
+                state: OnlinePurchaseState::from_int(get_u8(&mut payload)?)
+                    .ok_or(ProtocolError::WrongFormat)?,
+                items: get_items(&mut payload)?,
+            },
+            HELLO_KIND => WireMessage::Hello {
+                ecom: get_string(&mut payload)?,
+            },
+            WELCOME_KIND => WireMessage::Welcome {
+                shop: get_string(&mut payload)?,
+                zone_id: get_i32(&mut payload)?,
+                catalog_version: get_u32(&mut payload)?,
+            },
+            COMMIT_KIND => WireMessage::Commit {
+                id: get_order_id(&mut payload)?,
+            },
+            ABORT_KIND => WireMessage::Abort {
+                id: get_order_id(&mut payload)?,
+            },
+            CANCEL_KIND => WireMessage::Cancel {
+                id: get_order_id(&mut payload)?,
+            },
+            PING_KIND => WireMessage::Ping,
+            PONG_KIND => WireMessage::Pong,
+            RESTOCKED_KIND => WireMessage::Restocked {
+                shop: get_string(&mut payload)?,
+                items: get_items(&mut payload)?,
+            },
+            _ => return Err(ProtocolError::UnknownKind(kind)),
+        };
+
+        if payload.has_remaining() {
+            return Err(ProtocolError::WrongFormat);
+        }
+        Ok(message)
+    }
+}
+
+fn put_string(dst: &mut BytesMut, string: &str) -> Result<(), ProtocolError> {
+    let len = u16::try_from(string.len()).map_err(|_| ProtocolError::WrongFormat)?;
+    dst.put_u16(len);
+    dst.put_slice(string.as_bytes());
+    Ok(())
+}
+
+fn put_order_id(dst: &mut BytesMut, id: &OrderId) -> Result<(), ProtocolError> {
+    put_string(dst, &id.ecom)?;
+    dst.put_u32(id.sequence);
+    Ok(())
+}
+
+/// Writes the amount of items followed by the product and quantity of each one
+fn put_items(dst: &mut BytesMut, items: &[OrderItem]) -> Result<(), ProtocolError> {
+    let len = u16::try_from(items.len()).map_err(|_| ProtocolError::WrongFormat)?;
+    dst.put_u16(len);
+    for item in items {
+        put_string(dst, &item.product)?;
+        dst.put_u32(item.quantity);
+    }
+    Ok(())
+}
+
+fn get_items(src: &mut Bytes) -> Result<Vec<OrderItem>, ProtocolError> {
+    if src.remaining() < 2 {
+        return Err(ProtocolError::WrongFormat);
+    }
+    let len = src.get_u16();
+    (0..len)
+        .map(|_| {
+            Ok(OrderItem {
+                product: get_string(src)?,
+                quantity: get_u32(src)?,
+            })
+        })
+        .collect()
+}
+
+fn get_order_id(src: &mut Bytes) -> Result<OrderId, ProtocolError> {
+    Ok(OrderId {
+        ecom: get_string(src)?,
+        sequence: get_u32(src)?,
+    })
+}
+
+fn get_u8(src: &mut Bytes) -> Result<u8, ProtocolError> {
+    if src.remaining() < 1 {
+        return Err(ProtocolError::WrongFormat);
+    }
+    Ok(src.get_u8())
+}
+
+fn get_bool(src: &mut Bytes) -> Result<bool, ProtocolError> {
+    match get_u8(src)? {
+        0 => Ok(false),
+        1 => Ok(true),
+        _ => Err(ProtocolError::WrongFormat),
+    }
+}
+
+fn get_u32(src: &mut Bytes) -> Result<u32, ProtocolError> {
+    if src.remaining() < 4 {
+        return Err(ProtocolError::WrongFormat);
+    }
+    Ok(src.get_u32())
+}
+
+fn get_i32(src: &mut Bytes) -> Result<i32, ProtocolError> {
+    if src.remaining() < 4 {
+        return Err(ProtocolError::WrongFormat);
+    }
+    Ok(src.get_i32())
+}
+
+fn get_string(src: &mut Bytes) -> Result<String, ProtocolError> {
+    if src.remaining() < 2 {
+        return Err(ProtocolError::WrongFormat);
+    }
+    let len = src.get_u16() as usize;
+    if src.remaining() < len {
+        return Err(ProtocolError::WrongFormat);
+    }
+    String::from_utf8(src.split_to(len).to_vec()).map_err(|_| ProtocolError::WrongFormat)
+}
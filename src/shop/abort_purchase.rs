@@ -0,0 +1,81 @@
+use std::time::Duration;
+
+use actix::{AsyncContext, Context, Handler, Message};
+use colored::Colorize;
+
+use crate::{order_id::OrderId, order_item::describe, states::OnlinePurchaseState};
+
+use super::{
+    inventory_log::StockChange,
+    online_purchase::OnlinePurchase,
+    shop_actor::{PreparedPurchase, Shop},
+};
+
+#[derive(Debug, Message, Clone)]
+#[rtype(result = "()")]
+pub struct AbortPurchase {
+    pub id: OrderId,
+}
+
+impl Handler<AbortPurchase> for Shop {
+    type Result = ();
+
+    /// Second phase of an online purchase: gives back to the stock the prepared purchase
+    /// with the given id
+    fn handle(&mut self, msg: AbortPurchase, ctx: &mut Context<Self>) -> Self::Result {
+        match self.release_prepared(&msg.id, ctx) {
+            Some(mut purchase) => {
+                purchase.state = OnlinePurchaseState::CANCELLED;
+                self.record_state(&purchase);
+            }
+            None => println!("No hay reserva para el pedido {}", msg.id),
+        }
+    }
+}
+
+impl Shop {
+    /// Holds the reserved purchase until the ecom commits or aborts it. If the ecom does
+    /// not answer in time, the purchase is aborted and the ecom is told it was REJECTED.
+    pub fn prepare(&mut self, purchase: OnlinePurchase, ctx: &mut Context<Shop>) {
+        let id = purchase.id.clone();
+        let timeout_id = id.clone();
+        let timeout = ctx.run_later(
+            Duration::from_millis(self.config.prepare_timeout_millis),
+            move |shop, ctx| {
+                if let Some(mut purchase) = shop.release_prepared(&timeout_id, ctx) {
+                    purchase.state = OnlinePurchaseState::REJECTED;
+                    shop.record_state(&purchase);
+                    purchase.send_msg(ctx);
+                }
+            },
+        );
+
+        // una reserva anterior del mismo pedido se reemplaza por la nueva
+        self.release_prepared(&id, ctx);
+        self.prepared_purchases
+            .insert(id, PreparedPurchase { purchase, timeout });
+    }
+
+    /// Removes the prepared purchase with the given id and gives its items back to the stock
+    pub fn release_prepared(
+        &mut self,
+        id: &OrderId,
+        ctx: &mut Context<Shop>,
+    ) -> Option<OnlinePurchase> {
+        let prepared = self.prepared_purchases.remove(id)?;
+        ctx.cancel_future(prepared.timeout);
+        let purchase = prepared.purchase;
+
+        self.shards
+            .apply(purchase.items.clone(), StockChange::Released);
+
+        println!(
+            "[ECOM {}]  {} {}: {}",
+            purchase.ecom,
+            "LIBERADO".yellow(),
+            purchase.id,
+            describe(&purchase.items)
+        );
+        Some(purchase)
+    }
+}
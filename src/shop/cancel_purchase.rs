@@ -0,0 +1,145 @@
+use std::sync::Arc;
+
+use actix::{dev::ContextFutureSpawner, fut::wrap_future, Context, Handler, Message};
+use tokio::{io::WriteHalf, net::TcpStream, sync::Mutex};
+
+use crate::{
+    order_id::OrderId,
+    order_item::{describe, OrderItem},
+    protocol::{codec::send_message, wire_message::WireMessage},
+    states::OnlinePurchaseState,
+};
+
+use super::shop_actor::Shop;
+
+#[derive(Debug, Message, Clone)]
+#[rtype(result = "Result<OnlinePurchaseState, ()>")]
+pub struct CancelPurchase {
+    pub id: OrderId,
+    pub ecom: String,
+    pub write: Arc<Mutex<WriteHalf<TcpStream>>>,
+}
+
+impl Handler<CancelPurchase> for Shop {
+    type Result = Result<OnlinePurchaseState, ()>;
+
+    /// Retracts the online purchase with the given id:
+    /// - If it is prepared, its items go back to the stock and the ecom is told CANCELLED.
+    /// - If it is being delivered, the ecom is told TOO_LATE, with the items on their way.
+    /// - Otherwise, the shop holds nothing for it and the ecom is told CANCELLED.
+    fn handle(&mut self, msg: CancelPurchase, ctx: &mut Context<Self>) -> Self::Result {
+        let (state, items) = if let Some(mut purchase) = self.release_prepared(&msg.id, ctx) {
+            purchase.state = OnlinePurchaseState::CANCELLED;
+            self.record_state(&purchase);
+            (purchase.state, purchase.items)
+        } else if let Some(items) = self.delivering_purchases.get(&msg.id) {
+            (OnlinePurchaseState::TOO_LATE, items.clone())
+        } else {
+            (OnlinePurchaseState::CANCELLED, Vec::new())
+        };
+
+        println!(
+            "[ECOM {}]  {} {}: {}",
+            msg.ecom,
+            state.string_to_print(),
+            msg.id,
+            describe(&items)
+        );
+        msg.reply(state.clone(), items, ctx);
+        Ok(state)
+    }
+}
+
+impl CancelPurchase {
+    /// Sends the result of the cancellation to the ecommerce
+    fn reply(self, state: OnlinePurchaseState, items: Vec<OrderItem>, ctx: &mut Context<Shop>) {
+        wrap_future::<_, Shop>(async move {
+            let message = WireMessage::OrderState {
+                id: self.id,
+                state,
+                items,
+            };
+            if send_message(&self.write, message).await.is_err() {
+                println!("Error al enviar mensaje");
+            }
+        })
+        .wait(ctx);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use actix::Actor;
+    use tokio::io::split;
+
+    use super::*;
+    use crate::{
+        shop::{
+            commit_purchase::CommitPurchase, local_purchase::LocalPurchase,
+            online_purchase::OnlinePurchase, shop_actor::Product,
+        },
+        states::LocalPurchaseState,
+    };
+
+    #[actix_rt::test]
+    async fn test_cancel_prepared_then_committed_purchase() {
+        let shop = Shop::new(
+            "Tienda 1",
+            "localhost:9888",
+            1,
+            vec![Product {
+                id: "A".to_string(),
+                stock: 10,
+                reserved: 0,
+            }],
+        )
+        .start();
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            listener.accept().unwrap();
+        });
+        let stream = std::net::TcpStream::connect(address).unwrap();
+        let (_read, write) = split(TcpStream::from_std(stream).unwrap());
+        let write = Arc::new(Mutex::new(write));
+
+        let purchase = |sequence| OnlinePurchase {
+            id: OrderId::new("1", sequence),
+            zone_id: 1,
+            ecom: "1".to_string(),
+            write: write.clone(),
+            items: vec![OrderItem::new("A", 10)],
+            state: OnlinePurchaseState::RECEIVED,
+            allow_partial: false,
+        };
+        let cancel = |sequence| CancelPurchase {
+            id: OrderId::new("1", sequence),
+            ecom: "1".to_string(),
+            write: write.clone(),
+        };
+        let local = LocalPurchase {
+            product: "A".to_string(),
+            quantity: 10,
+            status: LocalPurchaseState::CREATED,
+        };
+
+        shop.send(purchase(1)).await.unwrap().unwrap();
+        let result = shop.send(cancel(1)).await.unwrap();
+        assert_eq!(result.unwrap(), OnlinePurchaseState::CANCELLED);
+
+        shop.send(purchase(2)).await.unwrap().unwrap();
+        shop.send(CommitPurchase {
+            id: OrderId::new("1", 2),
+        })
+        .await
+        .unwrap();
+        let result = shop.send(cancel(2)).await.unwrap();
+        assert_eq!(result.unwrap(), OnlinePurchaseState::TOO_LATE);
+
+        let result = shop.send(local).await.unwrap();
+        assert_eq!(result.unwrap(), LocalPurchaseState::REJECTED);
+    }
+}
@@ -0,0 +1,118 @@
+use actix::{ActorFutureExt, Context, Handler, Message, ResponseActFuture, WrapFuture};
+
+use crate::order_item::OrderItem;
+
+use super::{shop_actor::Shop, supplier::RequestRestock};
+
+/// Checks the stock of the given products after they were sold or reserved
+#[derive(Debug, Message)]
+#[rtype(result = "()")]
+pub struct CheckStock {
+    pub products: Vec<String>,
+}
+
+impl Handler<CheckStock> for Shop {
+    type Result = ResponseActFuture<Self, ()>;
+
+    /// Alerts of the products whose available stock dropped below their minimum, and asks
+    /// the supplier to restock them. A product is not reordered again until its restock
+    /// arrives
+    fn handle(&mut self, msg: CheckStock, _ctx: &mut Context<Self>) -> Self::Result {
+        let shards = self.shards.clone();
+
+        Box::pin(async move { shards.read().await }.into_actor(self).map(
+            move |stock, shop, _ctx| {
+                for product in stock.iter().filter(|p| msg.products.contains(&p.id)) {
+                    let point = match shop.reorder_points.get(&product.id) {
+                        Some(point) if product.stock < point.minimum => point.clone(),
+                        _ => continue,
+                    };
+                    if !shop.reordering.insert(product.id.clone()) {
+                        continue; // la reposicion ya fue pedida
+                    }
+                    println!(
+                        "[ALERTA] Stock bajo de {}: quedan {} (minimo {})",
+                        product.id, product.stock, point.minimum
+                    );
+                    if let Some(supplier) = &shop.supplier {
+                        supplier.do_send(RequestRestock {
+                            items: vec![OrderItem::new(&product.id, point.quantity)],
+                        });
+                    }
+                }
+            },
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use actix::Actor;
+
+    use super::*;
+    use crate::{
+        clock,
+        config::Config,
+        shop::{
+            local_purchase::LocalPurchase,
+            read_inventory::ReadInventory,
+            restock::Restock,
+            shop_actor::{Product, ReorderPoint},
+        },
+        states::LocalPurchaseState,
+    };
+
+    #[actix_rt::test]
+    async fn test_low_stock_is_reordered_once() {
+        let mut shop = Shop::new(
+            "Tienda 1",
+            "",
+            1,
+            vec![Product {
+                id: "A".to_string(),
+                stock: 5,
+                reserved: 0,
+            }],
+        );
+        shop.config = Config {
+            purchase_millis: 1,
+            supplier_millis: 100..=100,
+            ..Config::default()
+        };
+        let point = ReorderPoint {
+            minimum: 3,
+            quantity: 10,
+        };
+        shop.reorder_points.insert("A".to_string(), point);
+        let shop = shop.start();
+
+        // ambas ventas dejan el stock bajo el minimo, pero se repone una sola vez
+        for quantity in [3, 1] {
+            let purchase = LocalPurchase {
+                product: "A".to_string(),
+                quantity,
+                status: LocalPurchaseState::CREATED,
+            };
+            let state = shop.send(purchase).await.unwrap().unwrap();
+            assert_eq!(state, LocalPurchaseState::SOLD);
+        }
+        assert_eq!(shop.send(ReadInventory).await.unwrap()[0].stock, 1);
+
+        // una reposicion programada no es la pedida, asi que no se vuelve a pedir
+        let scheduled = Restock {
+            items: vec![OrderItem::new("A", 1)],
+            reorder: false,
+        };
+        shop.send(scheduled).await.unwrap();
+        shop.send(CheckStock {
+            products: vec!["A".to_string()],
+        })
+        .await
+        .unwrap();
+
+        clock::sleep(Duration::from_millis(400)).await;
+        assert_eq!(shop.send(ReadInventory).await.unwrap()[0].stock, 12);
+    }
+}
@@ -0,0 +1,33 @@
+use actix::{AsyncContext, Context, Handler, Message};
+
+use crate::order_id::OrderId;
+
+use super::{deliver_purchase::DeliverPurchase, shop_actor::Shop};
+
+#[derive(Debug, Message, Clone)]
+#[rtype(result = "()")]
+pub struct CommitPurchase {
+    pub id: OrderId,
+}
+
+impl Handler<CommitPurchase> for Shop {
+    type Result = ();
+
+    /// Second phase of an online purchase: sends the prepared purchase with the given id
+    /// to be delivered
+    fn handle(&mut self, msg: CommitPurchase, ctx: &mut Context<Self>) -> Self::Result {
+        match self.prepared_purchases.remove(&msg.id) {
+            Some(prepared) => {
+                ctx.cancel_future(prepared.timeout);
+                self.delivering_purchases.insert(
+                    prepared.purchase.id.clone(),
+                    prepared.purchase.items.clone(),
+                );
+                ctx.address().do_send(DeliverPurchase {
+                    purchase: prepared.purchase,
+                });
+            }
+            None => println!("No hay reserva para el pedido {}", msg.id),
+        }
+    }
+}
@@ -1,13 +1,12 @@
 use std::time::Duration;
 
-use actix::clock::sleep;
 use actix::{ActorFutureExt, Context, Handler, Message, ResponseActFuture, WrapFuture};
-use rand::{thread_rng, Rng};
+use rand::Rng;
 
-use crate::constants::DELIVER_MILLIS;
+use crate::clock;
 use crate::states::OnlinePurchaseState;
 
-use super::{online_purchase::OnlinePurchase, shop_actor::Shop};
+use super::{inventory_log::StockChange, online_purchase::OnlinePurchase, shop_actor::Shop};
 
 #[derive(Debug, Message, Clone)]
 #[rtype(result = "()")]
@@ -18,24 +17,38 @@ pub struct DeliverPurchase {
 impl Handler<DeliverPurchase> for Shop {
     type Result = ResponseActFuture<Self, ()>;
 
+    /// Simulates the delivery of the purchase, which takes longer the farther the customer is
+    /// from the shop. If there is no path to the customer's zone, the purchase gets LOST.
     fn handle(&mut self, mut msg: DeliverPurchase, _ctx: &mut Context<Self>) -> Self::Result {
-        let delivery_time = Duration::from_millis(thread_rng().gen_range(DELIVER_MILLIS));
+        let distance = self
+            .zones
+            .distance(self.location as i32, msg.purchase.zone_id.into());
+        let travel_millis =
+            u64::from(distance.unwrap_or(0)) * self.config.deliver_millis_per_distance;
+        let delivery_time = Duration::from_millis(
+            self.rng.gen_range(self.config.deliver_millis.clone()) + travel_millis,
+        );
 
         Box::pin(
-            sleep(delivery_time)
+            clock::sleep(delivery_time)
                 .into_actor(self)
                 .map(move |_msg, shop, ctx| {
-                    msg.purchase.state.deliver_attempt();
-
-                    let product = shop.stock.iter_mut().find(|p| p.id == msg.purchase.product);
-
-                    if let Some(product) = product {
-                        product.reserved -= msg.purchase.quantity;
-                        if msg.purchase.state == OnlinePurchaseState::LOST {
-                            product.stock += msg.purchase.quantity;
-                        }
+                    match distance {
+                        Some(_) => msg
+                            .purchase
+                            .state
+                            .deliver_attempt(&mut shop.rng, shop.config.deliver_rate),
+                        None => msg.purchase.state = OnlinePurchaseState::LOST,
                     }
 
+                    let change = match msg.purchase.state {
+                        OnlinePurchaseState::LOST => StockChange::Returned,
+                        _ => StockChange::Delivered,
+                    };
+                    shop.shards.apply(msg.purchase.items.clone(), change);
+
+                    shop.delivering_purchases.remove(&msg.purchase.id);
+                    shop.record_state(&msg.purchase);
                     msg.purchase.print_status();
                     msg.purchase.send_msg(ctx);
                 }),
@@ -0,0 +1,368 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{BufRead, BufReader, Read, Write},
+};
+
+use crate::error::{FileError, StockError};
+
+use super::shop_actor::Product;
+
+/// Change in the stock of a product
+#[derive(Debug, Clone, PartialEq)]
+pub enum StockChange {
+    /// Sold in a local purchase
+    Sold,
+    /// Reserved for an online purchase
+    Reserved,
+    /// Reservation given back to the stock
+    Released,
+    /// Reservation delivered to the customer
+    Delivered,
+    /// Reservation lost on delivery, given back to the stock
+    Returned,
+    /// Delivered by a supplier
+    Restocked,
+}
+
+impl StockChange {
+    fn name(&self) -> &str {
+        match self {
+            StockChange::Sold => "vendido",
+            StockChange::Reserved => "reservado",
+            StockChange::Released => "liberado",
+            StockChange::Delivered => "entregado",
+            StockChange::Returned => "devuelto",
+            StockChange::Restocked => "repuesto",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "vendido" => Some(StockChange::Sold),
+            "reservado" => Some(StockChange::Reserved),
+            "liberado" => Some(StockChange::Released),
+            "entregado" => Some(StockChange::Delivered),
+            "devuelto" => Some(StockChange::Returned),
+            "repuesto" => Some(StockChange::Restocked),
+            _ => None,
+        }
+    }
+
+    /// Applies the change of the given quantity to the product. If the product does not have
+    /// enough units in stock or reserved for it, or cannot hold that many, it is left as it was
+    pub fn apply(&self, product: &mut Product, quantity: u32) -> Result<(), StockError> {
+        let take_stock = || {
+            product
+                .stock
+                .checked_sub(quantity)
+                .ok_or(StockError::NotEnoughStock)
+        };
+        let take_reserved = || {
+            product
+                .reserved
+                .checked_sub(quantity)
+                .ok_or(StockError::NotEnoughReserved)
+        };
+        let (stock, reserved) = match self {
+            StockChange::Sold => (take_stock()?, product.reserved),
+            StockChange::Reserved => (take_stock()?, product.reserved + quantity),
+            StockChange::Released | StockChange::Returned => {
+                (product.stock + quantity, take_reserved()?)
+            }
+            StockChange::Delivered => (product.stock, take_reserved()?),
+            StockChange::Restocked => (
+                product
+                    .stock
+                    .checked_add(quantity)
+                    .ok_or(StockError::TooMuchStock)?,
+                product.reserved,
+            ),
+        };
+        product.stock = stock;
+        product.reserved = reserved;
+        Ok(())
+    }
+}
+
+/// Write-ahead log of the stock changes of a stock shard, on top of its last snapshot.
+/// Entries are numbered, and the snapshot keeps the number of the last entry it includes,
+/// so entries already in the snapshot are skipped if the log could not be emptied.
+#[derive(Debug)]
+pub struct InventoryLog {
+    log: File,
+    log_path: String,
+    snapshot_path: String,
+    /// Number of the last entry written
+    sequence: u64,
+    /// Entries written since the last snapshot
+    unsaved: u64,
+}
+
+impl InventoryLog {
+    fn paths(dir: &str, shop: &str, shard: usize) -> (String, String) {
+        (
+            format!("{}/{}_{}_snapshot.txt", dir, shop, shard),
+            format!("{}/{}_{}_log.txt", dir, shop, shard),
+        )
+    }
+
+    /// Restores the stock of the shop with the given name from the snapshots and logs of
+    /// its shards in the given directory, if it has any. The reservations do not survive a
+    /// restart, so their items go back to the stock.
+    pub fn restore(dir: &str, shop: &str) -> Result<Option<Vec<Product>>, FileError> {
+        let mut stock: Vec<Product> = Vec::new();
+        let mut shard = 0;
+
+        loop {
+            let (snapshot_path, log_path) = Self::paths(dir, shop, shard);
+            let snapshot = match File::open(&snapshot_path) {
+                Ok(snapshot) => snapshot,
+                Err(_) => break,
+            };
+            let (last, mut products) = Self::read_snapshot(snapshot)?;
+            if let Ok(log) = File::open(&log_path) {
+                Self::replay(log, last, &mut products)?;
+            }
+            stock.extend(products);
+            shard += 1;
+        }
+
+        if shard == 0 {
+            return Ok(None);
+        }
+        for product in stock.iter_mut() {
+            product.stock += product.reserved;
+            product.reserved = 0;
+        }
+        Ok(Some(stock))
+    }
+
+    /// Creates the log of the given shard of the shop in the given directory, starting from
+    /// a snapshot of its products
+    pub fn create(
+        dir: &str,
+        shop: &str,
+        shard: usize,
+        products: &[Product],
+    ) -> Result<Self, FileError> {
+        fs::create_dir_all(dir).map_err(|_| FileError::CannotWrite)?;
+        let (snapshot_path, log_path) = Self::paths(dir, shop, shard);
+        // la numeracion sigue a la del log anterior, asi sus entradas quedan en el snapshot
+        // aunque la tienda muera antes de vaciarlo
+        let sequence = Self::last_sequence(&snapshot_path, &log_path);
+        let log = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&log_path)
+            .map_err(|_| FileError::CannotWrite)?;
+
+        let mut inventory = Self {
+            log,
+            log_path,
+            snapshot_path,
+            sequence,
+            unsaved: 0,
+        };
+        inventory.snapshot(products)?;
+        Ok(inventory)
+    }
+
+    /// Number of the last entry in the snapshot and log in the given paths, if any
+    fn last_sequence(snapshot_path: &str, log_path: &str) -> u64 {
+        let snapshot = File::open(snapshot_path)
+            .ok()
+            .and_then(|file| Self::read_snapshot(file).ok())
+            .map_or(0, |(sequence, _)| sequence);
+        let log = File::open(log_path).ok().map_or(0, |file| {
+            BufReader::new(file)
+                .lines()
+                .map_while(Result::ok)
+                .filter_map(|line| Self::parse_entry(&line))
+                .map(|(sequence, ..)| sequence)
+                .max()
+                .unwrap_or(0)
+        });
+        snapshot.max(log)
+    }
+
+    /// Removes the files of the shards of the shop from the given one on, left by a run
+    /// with more shards
+    pub fn remove_from(dir: &str, shop: &str, shard: usize) {
+        let mut shard = shard;
+        loop {
+            let (snapshot_path, log_path) = Self::paths(dir, shop, shard);
+            if fs::remove_file(snapshot_path).is_err() {
+                break;
+            }
+            let _ = fs::remove_file(log_path);
+            shard += 1;
+        }
+    }
+
+    /// Writes the change to the log, and waits for it to reach the disk. Every change is
+    /// flushed on its own, so no change the shop acted on is lost if it dies, at the cost of
+    /// one disk flush per change: that flush bounds the changes per second of a shard, which
+    /// is why the stock is split in shards that flush in parallel
+    pub fn append(
+        &mut self,
+        change: &StockChange,
+        product: &str,
+        quantity: u32,
+    ) -> Result<(), FileError> {
+        writeln!(
+            self.log,
+            "{},{},{},{}",
+            self.sequence + 1,
+            change.name(),
+            product,
+            quantity
+        )
+        .map_err(|_| FileError::CannotWrite)?;
+        self.log.sync_data().map_err(|_| FileError::CannotWrite)?;
+        self.sequence += 1;
+        self.unsaved += 1;
+        Ok(())
+    }
+
+    /// Whether there are changes in the log that are not in the snapshot
+    pub fn has_unsaved(&self) -> bool {
+        self.unsaved > 0
+    }
+
+    /// Replaces the snapshot with the given products and empties the log. The new snapshot is
+    /// written aside and then renamed, so there is always a complete one.
+    pub fn snapshot(&mut self, products: &[Product]) -> Result<(), FileError> {
+        let temporary_path = format!("{}.tmp", self.snapshot_path);
+        let mut content = format!("{}\n---\n", self.sequence);
+        for product in products {
+            content.push_str(&format!(
+                "{},{},{}\n",
+                product.id, product.stock, product.reserved
+            ));
+        }
+
+        let mut temporary = File::create(&temporary_path).map_err(|_| FileError::CannotWrite)?;
+        temporary
+            .write_all(content.as_bytes())
+            .map_err(|_| FileError::CannotWrite)?;
+        temporary.sync_all().map_err(|_| FileError::CannotWrite)?;
+        fs::rename(&temporary_path, &self.snapshot_path).map_err(|_| FileError::CannotWrite)?;
+
+        self.log = File::create(&self.log_path).map_err(|_| FileError::CannotWrite)?;
+        self.unsaved = 0;
+        Ok(())
+    }
+
+    /// Reads a snapshot: the number of its last entry, a dash line and one
+    /// `<product>,<stock>,<reserved>` line per product
+    fn read_snapshot<T: Read>(content: T) -> Result<(u64, Vec<Product>), FileError> {
+        let mut lines = BufReader::new(content).lines();
+        let sequence = match lines.next() {
+            Some(line) => line
+                .map_err(|_| FileError::WrongFormat)?
+                .parse()
+                .map_err(|_| FileError::WrongFormat)?,
+            None => return Err(FileError::WrongFormat),
+        };
+        // ignore dash line
+        lines.next();
+
+        let mut stock = Vec::new();
+        for line in lines {
+            let current_line = line.map_err(|_| FileError::WrongFormat)?;
+            let product_data: Vec<&str> = current_line.split(',').collect();
+            // ['KEY', 'STOCK', 'RESERVED'].len() == 3
+            if product_data.len() != 3 {
+                return Err(FileError::WrongFormat);
+            }
+            stock.push(Product {
+                id: product_data[0].to_string(),
+                stock: product_data[1]
+                    .parse()
+                    .map_err(|_| FileError::WrongFormat)?,
+                reserved: product_data[2]
+                    .parse()
+                    .map_err(|_| FileError::WrongFormat)?,
+            });
+        }
+        Ok((sequence, stock))
+    }
+
+    /// Applies to the stock the entries of the log after the given one. A wrong last line
+    /// is ignored, as the shop may have died while writing it. Returns the last entry applied.
+    fn replay<T: Read>(content: T, after: u64, stock: &mut [Product]) -> Result<u64, FileError> {
+        let lines = BufReader::new(content)
+            .lines()
+            .collect::<Result<Vec<String>, _>>()
+            .map_err(|_| FileError::WrongFormat)?;
+        let mut last = after;
+
+        for (index, line) in lines.iter().enumerate() {
+            let entry = match Self::parse_entry(line) {
+                Some(entry) => entry,
+                None if index == lines.len() - 1 => break,
+                None => return Err(FileError::WrongFormat),
+            };
+            let (sequence, change, product, quantity) = entry;
+            if sequence <= last {
+                continue; // ya esta en el snapshot
+            }
+            if let Some(product) = stock.iter_mut().find(|p| p.id == product) {
+                // el log solo guarda cambios que se pudieron aplicar
+                change
+                    .apply(product, quantity)
+                    .map_err(|_| FileError::WrongFormat)?;
+            }
+            last = sequence;
+        }
+        Ok(last)
+    }
+
+    /// Parses a `<sequence>,<change>,<product>,<quantity>` line of the log
+    fn parse_entry(line: &str) -> Option<(u64, StockChange, String, u32)> {
+        match line.split(',').collect::<Vec<&str>>().as_slice() {
+            [sequence, change, product, quantity] => Some((
+                sequence.parse().ok()?,
+                StockChange::from_name(change)?,
+                product.to_string(),
+                quantity.parse().ok()?,
+            )),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+impl InventoryLog {
+    /// Reopens the log as read only, so the next writes fail
+    pub(crate) fn break_log(&mut self) {
+        self.log = File::open(&self.log_path).unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_replay_skips_entries_in_snapshot() {
+        let snapshot = "2\n---\nA,8,2\nB,5,0\n";
+        let log = "2,reservado,A,2\n\
+                   3,entregado,A,2\n\
+                   4,vendido,B,3\n\
+                   5,reservado,A,4\n\
+                   6,devuelto,A,4\n\
+                   7,repuesto,B,6\n\
+                   8,vendi";
+
+        let (last, mut stock) = InventoryLog::read_snapshot(snapshot.as_bytes()).unwrap();
+        let last = InventoryLog::replay(log.as_bytes(), last, &mut stock).unwrap();
+
+        assert_eq!(last, 7);
+        assert_eq!((stock[0].stock, stock[0].reserved), (8, 0));
+        assert_eq!((stock[1].stock, stock[1].reserved), (8, 0));
+
+        let broken = "3,vendido,B,1\n4,vendi\n5,vendido,B,1";
+        assert!(InventoryLog::replay(broken.as_bytes(), 2, &mut stock).is_err());
+    }
+}
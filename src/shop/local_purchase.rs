@@ -1,9 +1,11 @@
-use std::{thread, time::Duration};
+use std::time::Duration;
 
-use crate::{constants::PURCHASE_MILLIS, error::PurchaseError, states::LocalPurchaseState};
-use actix::{Context, Handler, Message};
+use crate::{clock, error::PurchaseError, order_item::OrderItem, states::LocalPurchaseState};
+use actix::{
+    ActorFutureExt, AsyncContext, Context, Handler, Message, ResponseActFuture, WrapFuture,
+};
 
-use super::shop_actor::Shop;
+use super::{check_stock::CheckStock, inventory_log::StockChange, shop_actor::Shop};
 
 #[derive(Debug, Message, Clone)]
 #[rtype(result = "Result<LocalPurchaseState, PurchaseError>")]
@@ -25,30 +27,43 @@ impl LocalPurchase {
 }
 
 impl Handler<LocalPurchase> for Shop {
-    type Result = Result<LocalPurchaseState, PurchaseError>;
+    type Result = ResponseActFuture<Self, Result<LocalPurchaseState, PurchaseError>>;
 
-    /// Processes the given local purchase:
+    /// Processes the given local purchase once the purchase time passes, without blocking
+    /// the shop meanwhile:
     /// - If the product is in stock, the purchase gets SOLD and the product is removed from the stock.
+    ///   Then the shop checks if the product has to be reordered.
     /// - If the product is not in stock, the purchase gets REJECTED.
+    ///
+    /// The stock is checked by the shard that owns the product, so purchases of the same
+    /// product never see the same stock.
     fn handle(&mut self, mut msg: LocalPurchase, _ctx: &mut Context<Self>) -> Self::Result {
-        thread::sleep(Duration::from_millis(PURCHASE_MILLIS));
-
-        match self.stock.iter_mut().find(|p| p.id == msg.product) {
-            Some(product) => {
-                if product.stock < msg.quantity {
-                    msg.status = LocalPurchaseState::REJECTED;
-                } else {
-                    msg.status = LocalPurchaseState::SOLD;
-                    product.stock -= msg.quantity;
-                }
-            }
-            None => {
-                msg.status = LocalPurchaseState::REJECTED;
+        let shards = self.shards.clone();
+        let item = OrderItem::new(&msg.product, msg.quantity);
+        let purchase_time = Duration::from_millis(self.config.purchase_millis);
+
+        Box::pin(
+            async move {
+                clock::sleep(purchase_time).await;
+                let taken = shards
+                    .take(vec![item.clone()], StockChange::Sold, false)
+                    .await;
+                taken == vec![item]
             }
-        };
-        msg.print_status();
-
-        Ok(msg.status.clone())
+            .into_actor(self)
+            .map(move |sold, _shop, ctx| {
+                msg.status = if sold {
+                    ctx.notify(CheckStock {
+                        products: vec![msg.product.clone()],
+                    });
+                    LocalPurchaseState::SOLD
+                } else {
+                    LocalPurchaseState::REJECTED
+                };
+                msg.print_status();
+                Ok(msg.status.clone())
+            }),
+        )
     }
 }
 
@@ -57,22 +72,22 @@ mod tests {
 
     use actix::Actor;
 
-    use crate::shop::shop_actor::Product;
+    use crate::{constants::PURCHASE_MILLIS, shop::shop_actor::Product};
 
     use super::*;
 
     #[actix_rt::test]
     async fn test_local_purchase_sold() {
-        let shop = Shop {
-            name: "Tienda 1".to_string(),
-            address: "localhost:9888".to_string(),
-            location: 1,
-            stock: vec![Product {
+        let shop = Shop::new(
+            "Tienda 1",
+            "localhost:9888",
+            1,
+            vec![Product {
                 id: "A".to_string(),
                 stock: 10,
                 reserved: 0,
             }],
-        }
+        )
         .start();
 
         let order = LocalPurchase {
@@ -88,16 +103,16 @@ mod tests {
 
     #[actix_rt::test]
     async fn test_local_purchase_no_stock() {
-        let shop = Shop {
-            name: "Tienda 1".to_string(),
-            address: "localhost:9888".to_string(),
-            location: 1,
-            stock: vec![Product {
+        let shop = Shop::new(
+            "Tienda 1",
+            "localhost:9888",
+            1,
+            vec![Product {
                 id: "A".to_string(),
                 stock: 10,
                 reserved: 0,
             }],
-        }
+        )
         .start();
 
         let order = LocalPurchase {
@@ -113,16 +128,16 @@ mod tests {
 
     #[actix_rt::test]
     async fn test_local_purchase_no_product() {
-        let shop = Shop {
-            name: "Tienda 1".to_string(),
-            address: "localhost:9888".to_string(),
-            location: 1,
-            stock: vec![Product {
+        let shop = Shop::new(
+            "Tienda 1",
+            "localhost:9888",
+            1,
+            vec![Product {
                 id: "A".to_string(),
                 stock: 10,
                 reserved: 0,
             }],
-        }
+        )
         .start();
 
         let order = LocalPurchase {
@@ -138,16 +153,16 @@ mod tests {
 
     #[actix_rt::test]
     async fn test_local_purchase_order_until_no_stock() {
-        let shop = Shop {
-            name: "Tienda 1".to_string(),
-            address: "localhost:9888".to_string(),
-            location: 1,
-            stock: vec![Product {
+        let shop = Shop::new(
+            "Tienda 1",
+            "localhost:9888",
+            1,
+            vec![Product {
                 id: "A".to_string(),
                 stock: 10,
                 reserved: 0,
             }],
-        }
+        )
         .start();
 
         let order1 = LocalPurchase {
@@ -180,4 +195,34 @@ mod tests {
 
         assert_eq!(result.unwrap(), LocalPurchaseState::REJECTED);
     }
+
+    #[actix_rt::test]
+    async fn test_concurrent_local_purchases_do_not_block_shop() {
+        let shop = Shop::new(
+            "Tienda 1",
+            "localhost:9888",
+            1,
+            vec![Product {
+                id: "A".to_string(),
+                stock: 10,
+                reserved: 0,
+            }],
+        )
+        .start();
+
+        let order = LocalPurchase {
+            product: "A".to_string(),
+            quantity: 6,
+            status: LocalPurchaseState::CREATED,
+        };
+
+        let start = std::time::Instant::now();
+        let (first, second) = futures::join!(shop.send(order.clone()), shop.send(order));
+
+        // ambas compras se procesan a la vez, pero solo una encuentra stock
+        assert!(start.elapsed() < Duration::from_millis(PURCHASE_MILLIS * 2));
+        let mut results = vec![first.unwrap().unwrap(), second.unwrap().unwrap()];
+        results.retain(|state| *state == LocalPurchaseState::SOLD);
+        assert_eq!(results.len(), 1);
+    }
 }
@@ -1,6 +1,19 @@
+pub mod abort_purchase;
+pub mod cancel_purchase;
+pub mod check_stock;
+pub mod commit_purchase;
 pub mod deliver_purchase;
+pub mod inventory_log;
 pub mod local_purchase;
 pub mod online_purchase;
+pub mod order_table;
 pub mod process_local_orders;
+pub mod read_inventory;
+pub mod read_ledger;
+pub mod register_ecom;
+pub mod restock;
 pub mod shop_actor;
 pub mod shop_server_side;
+pub mod stock_ledger;
+pub mod stock_shard;
+pub mod supplier;
@@ -1,116 +1,202 @@
-use std::{sync::Arc, thread, time::Duration};
+use std::{sync::Arc, time::Duration};
 
-use actix::{dev::ContextFutureSpawner, fut::wrap_future, AsyncContext, Context, Handler, Message};
-use tokio::{
-    io::{AsyncWriteExt, WriteHalf},
-    net::TcpStream,
-    sync::Mutex,
+use actix::{
+    dev::ContextFutureSpawner,
+    fut::{self, wrap_future},
+    ActorFutureExt, AsyncContext, Context, Handler, Message, ResponseActFuture, WrapFuture,
+};
+use tokio::{io::WriteHalf, net::TcpStream, sync::Mutex};
+
+use crate::{
+    clock,
+    error::StreamError,
+    order_id::OrderId,
+    order_item::{describe, OrderItem},
+    protocol::{codec::send_message, wire_message::WireMessage},
+    states::OnlinePurchaseState,
 };
 
-use crate::{constants::PURCHASE_MILLIS, error::StreamError, states::OnlinePurchaseState};
-
-use super::{deliver_purchase::DeliverPurchase, shop_actor::Shop};
+use super::{check_stock::CheckStock, inventory_log::StockChange, shop_actor::Shop};
 
 // Message
 #[derive(Debug, Message, Clone)]
 #[rtype(result = "Result<OnlinePurchaseState, ()>")]
 pub struct OnlinePurchase {
-    pub id: u8,
+    pub id: OrderId,
     pub ecom: String,
-    pub product: String,
-    pub quantity: u32,
+    pub items: Vec<OrderItem>,
     pub zone_id: u8,
     pub write: Arc<Mutex<WriteHalf<TcpStream>>>,
     pub state: OnlinePurchaseState,
+    pub allow_partial: bool,
 }
 
 impl Handler<OnlinePurchase> for Shop {
-    type Result = Result<OnlinePurchaseState, ()>;
-
-    /// Processes the given online purchase:
-    /// - If the product is in stock, the purchase status gets set as RESERVED and it is sent
-    /// as a message to be delivered to the ecommerce.
-    /// - If the product is not in stock, the purchase status gets set as REJECTED and it is
-    /// sent to the ecommerce.
-    fn handle(&mut self, mut msg: OnlinePurchase, ctx: &mut Context<Self>) -> Self::Result {
-        thread::sleep(Duration::from_millis(PURCHASE_MILLIS));
-        let product = self.stock.iter_mut().find(|p| p.id == msg.product);
-
-        match product {
-            Some(product) => {
-                if product.stock < msg.quantity {
-                    msg.state = OnlinePurchaseState::REJECTED;
-                } else {
-                    product.stock -= msg.quantity;
-                    product.reserved += msg.quantity;
-                    msg.state = OnlinePurchaseState::RESERVED;
-                }
-            }
-            None => {
-                msg.state = OnlinePurchaseState::REJECTED;
+    type Result = ResponseActFuture<Self, Result<OnlinePurchaseState, ()>>;
+
+    /// Processes the given online purchase once the purchase time passes, without blocking
+    /// the shop meanwhile. The stock is checked and reserved by the shards that own the
+    /// products, so purchases of the same product never see the same stock.
+    fn handle(&mut self, msg: OnlinePurchase, _ctx: &mut Context<Self>) -> Self::Result {
+        Box::pin(
+            clock::sleep(Duration::from_millis(self.config.purchase_millis))
+                .into_actor(self)
+                .then(move |_, shop, ctx| shop.process_online_purchase(msg, ctx)),
+        )
+    }
+}
+
+impl Shop {
+    /// Processes the given online purchase, as the first phase of the order:
+    /// - If every item is in stock, all of them get reserved and the purchase status gets set
+    ///   as RESERVED.
+    /// - If only part of the items is in stock and the purchase allows it, that part gets
+    ///   reserved and the status gets set as PARTIAL.
+    /// - Otherwise, nothing gets reserved and the purchase status gets set as REJECTED.
+    ///
+    /// The status is sent to the ecommerce, and reserved purchases are held until the
+    /// ecommerce commits or aborts them.
+    /// If the ecommerce already sent this same purchase, it gets the latest status again
+    /// and nothing new is reserved. If the purchase is still being reserved, the copy is
+    /// ignored.
+    fn process_online_purchase(
+        &mut self,
+        mut msg: OnlinePurchase,
+        ctx: &mut Context<Self>,
+    ) -> ResponseActFuture<Self, Result<OnlinePurchaseState, ()>> {
+        let prepared = &self.prepared_purchases;
+        let delivering = &self.delivering_purchases;
+        self.order_table.expire(clock::now(), |id| {
+            prepared.contains_key(id) || delivering.contains_key(id)
+        });
+        if let Some(processed) =
+            self.order_table
+                .duplicate(&msg.ecom, &msg.id, &msg.items, msg.allow_partial)
+        {
+            msg.state = processed.state.clone();
+            msg.items = processed.items.clone();
+            println!("[ECOM {}]  DUPLICADO {}", msg.ecom, msg.id);
+            msg.print_status();
+
+            let result = msg.state.clone();
+            msg.send_msg(ctx);
+            return Box::pin(fut::ready(Ok(result)));
+        }
+        if !self.purchases_in_progress.insert(msg.id.clone()) {
+            println!("[ECOM {}]  EN PROCESO {}", msg.ecom, msg.id);
+            return Box::pin(fut::ready(Err(())));
+        }
+
+        let shards = self.shards.clone();
+        let request = msg.items.clone();
+        let allow_partial = msg.allow_partial;
+        Box::pin(
+            async move {
+                shards
+                    .take(request, StockChange::Reserved, allow_partial)
+                    .await
             }
+            .into_actor(self)
+            .map(move |available, shop, ctx| shop.reserve_online_purchase(msg, available, ctx)),
+        )
+    }
+
+    /// Sets the state of the online purchase with the items the shards could reserve for
+    /// it, and sends it to the ecommerce
+    fn reserve_online_purchase(
+        &mut self,
+        mut msg: OnlinePurchase,
+        available: Vec<OrderItem>,
+        ctx: &mut Context<Self>,
+    ) -> Result<OnlinePurchaseState, ()> {
+        self.purchases_in_progress.remove(&msg.id);
+        let request = msg.items.clone();
+
+        if available == msg.items {
+            msg.state = OnlinePurchaseState::RESERVED;
+        } else if msg.allow_partial && available.iter().any(|item| item.quantity > 0) {
+            msg.items = available.into_iter().filter(|i| i.quantity > 0).collect();
+            msg.state = OnlinePurchaseState::PARTIAL;
+        } else {
+            msg.state = OnlinePurchaseState::REJECTED;
         }
+
         msg.print_status();
+        self.order_table.record_request(
+            &msg.ecom,
+            msg.id.clone(),
+            request,
+            msg.allow_partial,
+            msg.state.clone(),
+            msg.items.clone(),
+        );
 
         let result = msg.state.clone();
-        // si fue rechazado, se envia el rechazo
-        if msg.state == OnlinePurchaseState::REJECTED {
-            msg.send_msg(ctx);
-            return Ok(result);
+        match msg.state {
+            // si fue rechazado, se envia el rechazo
+            OnlinePurchaseState::REJECTED => msg.send_msg(ctx),
+            // si fue reservado, se guarda hasta que el ecom haga commit o abort
+            _ => {
+                ctx.notify(CheckStock {
+                    products: msg.items.iter().map(|item| item.product.clone()).collect(),
+                });
+                msg.clone().send_msg(ctx);
+                self.prepare(msg, ctx);
+            }
         }
 
-        ctx.address().do_send(DeliverPurchase { purchase: msg });
-
         Ok(result)
     }
 }
 
 impl OnlinePurchase {
-    /// Parses the given line into an OnlinePurchase
+    /// Parses the given order message into an OnlinePurchase
     pub fn parse(
-        line: Vec<&str>,
+        message: WireMessage,
         ecom: String,
         write_half: Arc<Mutex<WriteHalf<TcpStream>>>,
     ) -> Result<OnlinePurchase, StreamError> {
-        let id = line[0]
-            .parse::<u8>()
-            .map_err(|_| StreamError::WrongFormat)?;
-
-        let product = line[1].to_string();
-        let quantity = line[2]
-            .parse::<u32>()
-            .map_err(|_| StreamError::WrongFormat)?;
-        let zone_id = line[3]
-            .parse::<u8>()
-            .map_err(|_| StreamError::WrongFormat)?;
+        let (id, items, zone_id, allow_partial) = match message {
+            WireMessage::Prepare {
+                id,
+                items,
+                zone_id,
+                allow_partial,
+            } => (id, items, zone_id, allow_partial),
+            _ => return Err(StreamError::WrongFormat),
+        };
+        let zone_id = u8::try_from(zone_id).map_err(|_| StreamError::WrongFormat)?;
 
         Ok(OnlinePurchase {
             id,
             ecom,
-            product,
-            quantity,
+            items,
             zone_id,
             write: write_half,
             state: OnlinePurchaseState::RECEIVED,
+            allow_partial,
         })
     }
 
     pub fn print_status(&self) {
         println!(
-            "[ECOM {}]  {} {:>2} x {}",
+            "[ECOM {}]  {} {}: {}",
             self.ecom,
             self.state.string_to_print(),
-            self.quantity,
-            self.product
+            self.id,
+            describe(&self.items)
         );
     }
 
-    /// Sends the message with format "{purchase_id},{purchase_status}\n" to the ecommerce
+    /// Sends the current state of the purchase to the ecommerce
     pub fn send_msg(self, ctx: &mut Context<Shop>) {
         wrap_future::<_, Shop>(async move {
-            let mut write = self.write.lock().await;
-            let msg = format!("{},{}\n", self.id, self.state.to_int());
-            if write.write_all(msg.as_bytes()).await.is_err() {
+            let message = WireMessage::OrderState {
+                id: self.id,
+                state: self.state,
+                items: self.items,
+            };
+            if send_message(&self.write, message).await.is_err() {
                 println!("Error al enviar mensaje");
             }
         })
@@ -120,72 +206,76 @@ impl OnlinePurchase {
 
 #[cfg(test)]
 mod tests {
+    use std::thread;
+
     use actix::Actor;
     use tokio::io::split;
 
     use super::*;
-    use crate::{ecom::ecom_actor::EcomOrder, shop::shop_actor::Product};
+    use crate::{
+        ecom::ecom_actor::EcomOrder,
+        shop::{abort_purchase::AbortPurchase, local_purchase::LocalPurchase, shop_actor::Product},
+        states::LocalPurchaseState,
+    };
 
     #[actix_rt::test]
     async fn test_parsing_online_purchase() {
-        let order = EcomOrder {
-            id: 1,
-            product_id: String::from("manzana"),
-            quantity: 1,
-            zone_id: 1,
-            shops_requested: Vec::new(),
-        };
-        let mut line: String = order.as_string();
+        let order = EcomOrder::new(
+            OrderId::new("1", 1),
+            vec![OrderItem::new("manzana", 1), OrderItem::new("banana", 2)],
+            1,
+        );
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
         thread::spawn(move || {
-            let listener = std::net::TcpListener::bind("127.0.0.1:18500").unwrap();
             listener.accept().unwrap();
         });
-        thread::sleep(Duration::from_millis(100));
-        let stream = std::net::TcpStream::connect("127.0.0.1:18500").unwrap();
+        let stream = std::net::TcpStream::connect(address).unwrap();
         let tokio_stream = TcpStream::from_std(stream).unwrap();
         let (_read, write) = split(tokio_stream);
-        line.truncate(line.len() - 1);
-        let message = line.split(',').collect::<Vec<&str>>();
-        let purchase =
-            OnlinePurchase::parse(message, "2".to_string(), Arc::new(Mutex::new(write))).unwrap();
-        assert_eq!(order.id, purchase.id.into());
-        assert_eq!(order.product_id, purchase.product);
+        let purchase = OnlinePurchase::parse(
+            order.to_message(),
+            "2".to_string(),
+            Arc::new(Mutex::new(write)),
+        )
+        .unwrap();
+        assert_eq!(order.id, purchase.id);
+        assert_eq!(order.items, purchase.items);
         assert_eq!("2".to_string(), purchase.ecom);
-        assert_eq!(order.quantity, purchase.quantity);
         assert_eq!(order.zone_id, purchase.zone_id.into());
     }
 
     #[actix_rt::test]
     async fn test_online_purchase_sold() {
-        let shop = Shop {
-            name: "Tienda 1".to_string(),
-            address: "localhost:9888".to_string(),
-            location: 1,
-            stock: vec![Product {
+        let shop = Shop::new(
+            "Tienda 1",
+            "localhost:9888",
+            1,
+            vec![Product {
                 id: "A".to_string(),
                 stock: 10,
                 reserved: 0,
             }],
-        }
+        )
         .start();
 
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
         thread::spawn(move || {
-            let listener = std::net::TcpListener::bind("127.0.0.1:28510").unwrap();
             listener.accept().unwrap();
         });
-        thread::sleep(Duration::from_millis(100));
-        let stream = std::net::TcpStream::connect("127.0.0.1:28510").unwrap();
+        let stream = std::net::TcpStream::connect(address).unwrap();
         let tokio_stream = TcpStream::from_std(stream).unwrap();
         let (_read, write) = split(tokio_stream);
 
         let order = OnlinePurchase {
-            id: 1,
+            id: OrderId::new("1", 1),
             ecom: "1".to_string(),
             zone_id: 1,
             write: Arc::new(Mutex::new(write)),
-            product: "A".to_string(),
-            quantity: 1,
+            items: vec![OrderItem::new("A", 1)],
             state: OnlinePurchaseState::RECEIVED,
+            allow_partial: false,
         };
 
         let result = shop.send(order).await.unwrap();
@@ -195,35 +285,35 @@ mod tests {
 
     #[actix_rt::test]
     async fn test_online_purchase_no_stock() {
-        let shop = Shop {
-            name: "Tienda 1".to_string(),
-            address: "localhost:9888".to_string(),
-            location: 1,
-            stock: vec![Product {
+        let shop = Shop::new(
+            "Tienda 1",
+            "localhost:9888",
+            1,
+            vec![Product {
                 id: "A".to_string(),
                 stock: 10,
                 reserved: 0,
             }],
-        }
+        )
         .start();
 
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
         thread::spawn(move || {
-            let listener = std::net::TcpListener::bind("127.0.0.1:28501").unwrap();
             listener.accept().unwrap();
         });
-        thread::sleep(Duration::from_millis(100));
-        let stream = std::net::TcpStream::connect("127.0.0.1:28501").unwrap();
+        let stream = std::net::TcpStream::connect(address).unwrap();
         let tokio_stream = TcpStream::from_std(stream).unwrap();
         let (_read, write) = split(tokio_stream);
 
         let order = OnlinePurchase {
-            id: 1,
+            id: OrderId::new("1", 1),
             zone_id: 1,
             ecom: "1".to_string(),
             write: Arc::new(Mutex::new(write)),
-            product: "A".to_string(),
-            quantity: 11,
+            items: vec![OrderItem::new("A", 11)],
             state: OnlinePurchaseState::RECEIVED,
+            allow_partial: false,
         };
 
         let result = shop.send(order).await.unwrap();
@@ -233,35 +323,35 @@ mod tests {
 
     #[actix_rt::test]
     async fn test_online_purchase_no_product() {
-        let shop = Shop {
-            name: "Tienda 1".to_string(),
-            address: "localhost:9888".to_string(),
-            location: 1,
-            stock: vec![Product {
+        let shop = Shop::new(
+            "Tienda 1",
+            "localhost:9888",
+            1,
+            vec![Product {
                 id: "A".to_string(),
                 stock: 10,
                 reserved: 0,
             }],
-        }
+        )
         .start();
 
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
         thread::spawn(move || {
-            let listener = std::net::TcpListener::bind("127.0.0.1:28502").unwrap();
             listener.accept().unwrap();
         });
-        thread::sleep(Duration::from_millis(100));
-        let stream = std::net::TcpStream::connect("127.0.0.1:28502").unwrap();
+        let stream = std::net::TcpStream::connect(address).unwrap();
         let tokio_stream = TcpStream::from_std(stream).unwrap();
         let (_read, write) = split(tokio_stream);
 
         let order = OnlinePurchase {
-            id: 1,
+            id: OrderId::new("1", 1),
             zone_id: 1,
             ecom: "1".to_string(),
             write: Arc::new(Mutex::new(write)),
-            product: "B".to_string(),
-            quantity: 1,
+            items: vec![OrderItem::new("B", 1)],
             state: OnlinePurchaseState::RECEIVED,
+            allow_partial: false,
         };
 
         let result = shop.send(order).await.unwrap();
@@ -271,65 +361,222 @@ mod tests {
 
     #[actix_rt::test]
     async fn test_online_purchase_order_until_no_stock() {
-        let shop = Shop {
-            name: "Tienda 1".to_string(),
-            address: "localhost:9888".to_string(),
-            location: 1,
-            stock: vec![Product {
+        let shop = Shop::new(
+            "Tienda 1",
+            "localhost:9888",
+            1,
+            vec![Product {
                 id: "A".to_string(),
                 stock: 10,
                 reserved: 0,
             }],
-        }
+        )
         .start();
 
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
         thread::spawn(move || {
-            let listener = std::net::TcpListener::bind("127.0.0.1:28503").unwrap();
             listener.accept().unwrap();
         });
-        thread::sleep(Duration::from_millis(100));
-        let stream = std::net::TcpStream::connect("127.0.0.1:28503").unwrap();
+        let stream = std::net::TcpStream::connect(address).unwrap();
         let tokio_stream = TcpStream::from_std(stream).unwrap();
         let (_read, write) = split(tokio_stream);
         let write = Arc::new(Mutex::new(write));
 
         let order = OnlinePurchase {
-            id: 1,
+            id: OrderId::new("1", 1),
             zone_id: 1,
             ecom: "1".to_string(),
             write: write.clone(),
-            product: "A".to_string(),
-            quantity: 4,
+            items: vec![OrderItem::new("A", 4)],
             state: OnlinePurchaseState::RECEIVED,
+            allow_partial: false,
         };
 
         let result = shop.send(order).await.unwrap();
         assert_eq!(result.unwrap(), OnlinePurchaseState::RESERVED);
 
         let order2 = OnlinePurchase {
-            id: 1,
+            id: OrderId::new("1", 2),
             zone_id: 1,
             ecom: "1".to_string(),
             write: write.clone(),
-            product: "A".to_string(),
-            quantity: 4,
+            items: vec![OrderItem::new("A", 4)],
             state: OnlinePurchaseState::RECEIVED,
+            allow_partial: false,
         };
 
         let result = shop.send(order2).await.unwrap();
         assert_eq!(result.unwrap(), OnlinePurchaseState::RESERVED);
 
         let order3 = OnlinePurchase {
-            id: 1,
+            id: OrderId::new("1", 3),
             zone_id: 1,
             ecom: "1".to_string(),
             write: write.clone(),
-            product: "A".to_string(),
-            quantity: 4,
+            items: vec![OrderItem::new("A", 4)],
             state: OnlinePurchaseState::RECEIVED,
+            allow_partial: false,
         };
 
         let result = shop.send(order3).await.unwrap();
         assert_eq!(result.unwrap(), OnlinePurchaseState::REJECTED);
     }
+
+    #[actix_rt::test]
+    async fn test_online_purchase_partial_then_released() {
+        let shop = Shop::new(
+            "Tienda 1",
+            "localhost:9888",
+            1,
+            vec![Product {
+                id: "A".to_string(),
+                stock: 10,
+                reserved: 0,
+            }],
+        )
+        .start();
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            listener.accept().unwrap();
+        });
+        let stream = std::net::TcpStream::connect(address).unwrap();
+        let tokio_stream = TcpStream::from_std(stream).unwrap();
+        let (_read, write) = split(tokio_stream);
+
+        let order = OnlinePurchase {
+            id: OrderId::new("1", 1),
+            zone_id: 1,
+            ecom: "1".to_string(),
+            write: Arc::new(Mutex::new(write)),
+            items: vec![OrderItem::new("A", 15)],
+            state: OnlinePurchaseState::RECEIVED,
+            allow_partial: true,
+        };
+
+        let result = shop.send(order).await.unwrap();
+        assert_eq!(result.unwrap(), OnlinePurchaseState::PARTIAL);
+
+        let local = LocalPurchase {
+            product: "A".to_string(),
+            quantity: 10,
+            status: LocalPurchaseState::CREATED,
+        };
+        let result = shop.send(local.clone()).await.unwrap();
+        assert_eq!(result.unwrap(), LocalPurchaseState::REJECTED);
+
+        shop.send(AbortPurchase {
+            id: OrderId::new("1", 1),
+        })
+        .await
+        .unwrap();
+
+        let result = shop.send(local).await.unwrap();
+        assert_eq!(result.unwrap(), LocalPurchaseState::SOLD);
+    }
+
+    #[actix_rt::test]
+    async fn test_online_purchase_cart_reserves_all_or_nothing() {
+        let shop = Shop::new(
+            "Tienda 1",
+            "localhost:9888",
+            1,
+            vec![
+                Product {
+                    id: "A".to_string(),
+                    stock: 10,
+                    reserved: 0,
+                },
+                Product {
+                    id: "B".to_string(),
+                    stock: 1,
+                    reserved: 0,
+                },
+            ],
+        )
+        .start();
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            listener.accept().unwrap();
+        });
+        let stream = std::net::TcpStream::connect(address).unwrap();
+        let tokio_stream = TcpStream::from_std(stream).unwrap();
+        let (_read, write) = split(tokio_stream);
+        let write = Arc::new(Mutex::new(write));
+
+        let order = OnlinePurchase {
+            id: OrderId::new("1", 1),
+            zone_id: 1,
+            ecom: "1".to_string(),
+            write: write.clone(),
+            items: vec![OrderItem::new("A", 10), OrderItem::new("B", 2)],
+            state: OnlinePurchaseState::RECEIVED,
+            allow_partial: false,
+        };
+        let result = shop.send(order).await.unwrap();
+        assert_eq!(result.unwrap(), OnlinePurchaseState::REJECTED);
+
+        let order = OnlinePurchase {
+            id: OrderId::new("1", 2),
+            zone_id: 1,
+            ecom: "1".to_string(),
+            write,
+            items: vec![OrderItem::new("A", 10), OrderItem::new("B", 1)],
+            state: OnlinePurchaseState::RECEIVED,
+            allow_partial: false,
+        };
+        let result = shop.send(order).await.unwrap();
+        assert_eq!(result.unwrap(), OnlinePurchaseState::RESERVED);
+    }
+
+    #[actix_rt::test]
+    async fn test_online_purchase_duplicate_is_not_reserved_again() {
+        let shop = Shop::new(
+            "Tienda 1",
+            "localhost:9888",
+            1,
+            vec![Product {
+                id: "A".to_string(),
+                stock: 10,
+                reserved: 0,
+            }],
+        )
+        .start();
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            listener.accept().unwrap();
+        });
+        let stream = std::net::TcpStream::connect(address).unwrap();
+        let tokio_stream = TcpStream::from_std(stream).unwrap();
+        let (_read, write) = split(tokio_stream);
+
+        let order = OnlinePurchase {
+            id: OrderId::new("1", 1),
+            zone_id: 1,
+            ecom: "1".to_string(),
+            write: Arc::new(Mutex::new(write)),
+            items: vec![OrderItem::new("A", 6)],
+            state: OnlinePurchaseState::RECEIVED,
+            allow_partial: false,
+        };
+
+        let result = shop.send(order.clone()).await.unwrap();
+        assert_eq!(result.unwrap(), OnlinePurchaseState::RESERVED);
+        let result = shop.send(order).await.unwrap();
+        assert_eq!(result.unwrap(), OnlinePurchaseState::RESERVED);
+
+        let local = LocalPurchase {
+            product: "A".to_string(),
+            quantity: 4,
+            status: LocalPurchaseState::CREATED,
+        };
+        let result = shop.send(local).await.unwrap();
+        assert_eq!(result.unwrap(), LocalPurchaseState::SOLD);
+    }
 }
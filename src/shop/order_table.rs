@@ -0,0 +1,158 @@
+use std::{collections::HashMap, time::Duration};
+
+use crate::{
+    clock::{self, Instant},
+    order_id::OrderId,
+    order_item::OrderItem,
+    states::OnlinePurchaseState,
+};
+
+/// Online order already processed by the shop, with the latest state sent to the ecom
+#[derive(Debug, Clone)]
+pub struct ProcessedOrder {
+    /// Items requested by the ecom
+    pub request: Vec<OrderItem>,
+    pub allow_partial: bool,
+    pub state: OnlinePurchaseState,
+    /// Items the latest state refers to
+    pub items: Vec<OrderItem>,
+    pub updated_at: Instant,
+}
+
+/// Orders processed by the shop for each ecom, used to answer retransmitted orders
+/// without reserving the stock again. Entries expire after a while without updates.
+#[derive(Debug)]
+pub struct OrderTable {
+    orders: HashMap<String, HashMap<OrderId, ProcessedOrder>>,
+    expiration: Duration,
+}
+
+impl OrderTable {
+    pub fn new(expiration: Duration) -> Self {
+        Self {
+            orders: HashMap::new(),
+            expiration,
+        }
+    }
+
+    /// Returns the processed order if the ecom already sent this same request for the id
+    pub fn duplicate(
+        &self,
+        ecom: &str,
+        id: &OrderId,
+        request: &[OrderItem],
+        allow_partial: bool,
+    ) -> Option<&ProcessedOrder> {
+        self.orders
+            .get(ecom)?
+            .get(id)
+            .filter(|order| order.request == request && order.allow_partial == allow_partial)
+    }
+
+    /// Stores the answer given to a new request of the ecom
+    pub fn record_request(
+        &mut self,
+        ecom: &str,
+        id: OrderId,
+        request: Vec<OrderItem>,
+        allow_partial: bool,
+        state: OnlinePurchaseState,
+        items: Vec<OrderItem>,
+    ) {
+        self.orders.entry(ecom.to_string()).or_default().insert(
+            id,
+            ProcessedOrder {
+                request,
+                allow_partial,
+                state,
+                items,
+                updated_at: clock::now(),
+            },
+        );
+    }
+
+    /// Updates the latest state of an order already processed
+    pub fn update_state(
+        &mut self,
+        ecom: &str,
+        id: &OrderId,
+        state: OnlinePurchaseState,
+        items: Vec<OrderItem>,
+    ) {
+        if let Some(order) = self
+            .orders
+            .get_mut(ecom)
+            .and_then(|orders| orders.get_mut(id))
+        {
+            order.state = state;
+            order.items = items;
+            order.updated_at = clock::now();
+        }
+    }
+
+    /// Removes the orders not updated in the expiration window, except the ones to be kept
+    pub fn expire<F: Fn(&OrderId) -> bool>(&mut self, now: Instant, keep: F) {
+        let expiration = self.expiration;
+        for orders in self.orders.values_mut() {
+            orders.retain(|id, order| {
+                keep(id) || now.saturating_duration_since(order.updated_at) < expiration
+            });
+        }
+        self.orders.retain(|_, orders| !orders.is_empty());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table_with_order() -> OrderTable {
+        let mut table = OrderTable::new(Duration::from_millis(100));
+        table.record_request(
+            "ecom1",
+            OrderId::new("ecom1", 1),
+            vec![OrderItem::new("A", 5)],
+            false,
+            OnlinePurchaseState::RESERVED,
+            vec![OrderItem::new("A", 5)],
+        );
+        table
+    }
+
+    #[test]
+    fn test_duplicate_request() {
+        let mut table = table_with_order();
+        let id = OrderId::new("ecom1", 1);
+        let request = vec![OrderItem::new("A", 5)];
+
+        assert!(table.duplicate("ecom1", &id, &request, false).is_some());
+        assert!(table.duplicate("ecom1", &id, &request, true).is_none());
+        assert!(table.duplicate("ecom2", &id, &request, false).is_none());
+        assert!(table
+            .duplicate("ecom1", &id, &[OrderItem::new("A", 4)], false)
+            .is_none());
+
+        table.update_state(
+            "ecom1",
+            &id,
+            OnlinePurchaseState::DELIVERED,
+            request.clone(),
+        );
+        let order = table.duplicate("ecom1", &id, &request, false).unwrap();
+        assert_eq!(order.state, OnlinePurchaseState::DELIVERED);
+    }
+
+    #[test]
+    fn test_expired_orders_are_removed() {
+        let mut table = table_with_order();
+        let id = OrderId::new("ecom1", 1);
+        let request = vec![OrderItem::new("A", 5)];
+        let later = clock::now() + Duration::from_millis(200);
+
+        table.expire(later, |order_id| *order_id == id);
+        assert!(table.duplicate("ecom1", &id, &request, false).is_some());
+
+        table.expire(later, |_| false);
+        assert!(table.duplicate("ecom1", &id, &request, false).is_none());
+    }
+}
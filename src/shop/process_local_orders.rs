@@ -1,10 +1,9 @@
 use std::time::Duration;
 
 use actix::{dev::ContextFutureSpawner, fut::wrap_future, AsyncContext, Handler, Message};
-use rand::{thread_rng, Rng};
-use tokio::time::sleep;
+use rand::Rng;
 
-use crate::{constants::LOCAL_PROCESING_MILLIS, shop::shop_actor::Shop};
+use crate::{clock, shop::shop_actor::Shop};
 
 use super::local_purchase::LocalPurchase;
 
@@ -23,12 +22,12 @@ impl Handler<ProcessLocalOrders> for Shop {
             None => return,
         };
         let address = ctx.address().clone();
+        let millis = self
+            .rng
+            .gen_range(self.config.local_procesing_millis.clone());
 
         wrap_future::<_, Self>(async move {
-            sleep(Duration::from_millis(
-                thread_rng().gen_range(LOCAL_PROCESING_MILLIS),
-            ))
-            .await;
+            clock::sleep(Duration::from_millis(millis)).await;
             address.do_send(next_order);
 
             msg.0.remove(0);
@@ -0,0 +1,22 @@
+use actix::{Context, Handler, Message, ResponseFuture};
+
+use super::shop_actor::{Product, Shop};
+
+/// Returns the stock of the shop, with the quantities reserved for online purchases, sorted
+/// by product
+#[derive(Debug, Message)]
+#[rtype(result = "Vec<Product>")]
+pub struct ReadInventory;
+
+impl Handler<ReadInventory> for Shop {
+    type Result = ResponseFuture<Vec<Product>>;
+
+    fn handle(&mut self, _msg: ReadInventory, _ctx: &mut Context<Self>) -> Self::Result {
+        let shards = self.shards.clone();
+        Box::pin(async move {
+            let mut stock = shards.read().await;
+            stock.sort_by(|a, b| a.id.cmp(&b.id));
+            stock
+        })
+    }
+}
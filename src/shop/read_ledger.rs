@@ -0,0 +1,24 @@
+use actix::{Context, Handler, Message, ResponseFuture};
+
+use super::{
+    shop_actor::{Product, Shop},
+    stock_ledger::ProductLedger,
+};
+
+/// Returns the stock of the shop along with the ledger of each product, sorted by product
+#[derive(Debug, Message)]
+#[rtype(result = "Vec<(Product, ProductLedger)>")]
+pub struct ReadLedger;
+
+impl Handler<ReadLedger> for Shop {
+    type Result = ResponseFuture<Vec<(Product, ProductLedger)>>;
+
+    fn handle(&mut self, _msg: ReadLedger, _ctx: &mut Context<Self>) -> Self::Result {
+        let shards = self.shards.clone();
+        Box::pin(async move {
+            let mut ledger = shards.ledger().await;
+            ledger.sort_by(|(a, _), (b, _)| a.id.cmp(&b.id));
+            ledger
+        })
+    }
+}
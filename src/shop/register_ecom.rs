@@ -0,0 +1,23 @@
+use std::sync::Arc;
+
+use actix::{Context, Handler, Message};
+use tokio::{io::WriteHalf, net::TcpStream, sync::Mutex};
+
+use super::shop_actor::Shop;
+
+/// Registers the connection of an ecom once it identified itself, replacing the previous
+/// one of the same ecom
+#[derive(Debug, Message)]
+#[rtype(result = "()")]
+pub struct RegisterEcom {
+    pub ecom: String,
+    pub write: Arc<Mutex<WriteHalf<TcpStream>>>,
+}
+
+impl Handler<RegisterEcom> for Shop {
+    type Result = ();
+
+    fn handle(&mut self, msg: RegisterEcom, _ctx: &mut Context<Self>) -> Self::Result {
+        self.ecoms.insert(msg.ecom, msg.write);
+    }
+}
@@ -0,0 +1,141 @@
+use std::sync::Arc;
+
+use actix::{
+    dev::ContextFutureSpawner, fut::wrap_future, ActorFutureExt, Context, Handler, Message,
+    MessageResult,
+};
+
+use crate::{
+    order_item::{describe, OrderItem},
+    protocol::{codec::send_message, wire_message::WireMessage},
+};
+
+use super::{inventory_log::StockChange, shop_actor::Shop};
+
+/// Items delivered to the shop by a supplier
+#[derive(Debug, Message, Clone)]
+#[rtype(result = "Vec<OrderItem>")]
+pub struct Restock {
+    pub items: Vec<OrderItem>,
+    /// Whether the shop asked for it when the products ran low, rather than being scheduled
+    pub reorder: bool,
+}
+
+impl Handler<Restock> for Shop {
+    type Result = MessageResult<Restock>;
+
+    /// Adds the items to the stock and reports them to every connected ecom. If the shop
+    /// reordered them, their products can be reordered again. Items of products the shop
+    /// does not sell are left out. Returns the items restocked
+    fn handle(&mut self, msg: Restock, ctx: &mut Context<Self>) -> Self::Result {
+        let (items, unknown): (Vec<OrderItem>, Vec<OrderItem>) = msg
+            .items
+            .into_iter()
+            .filter(|item| item.quantity > 0)
+            .partition(|item| self.sells(&item.product));
+        for item in &unknown {
+            println!("ERROR: la tienda no vende {}", item.product);
+        }
+        if items.is_empty() {
+            return MessageResult(items);
+        }
+
+        println!("[PROVEEDOR] Reposicion: {}", describe(&items));
+        // las reposiciones programadas no responden a un pedido de la tienda
+        if msg.reorder {
+            for item in &items {
+                self.reordering.remove(&item.product);
+            }
+        }
+        self.shards.apply(items.clone(), StockChange::Restocked);
+
+        for (ecom, write) in self.ecoms.clone() {
+            let message = WireMessage::Restocked {
+                shop: self.name.clone(),
+                items: items.clone(),
+            };
+            let sent_to = write.clone();
+            wrap_future::<_, Self>(async move { send_message(&sent_to, message).await.is_ok() })
+                .map(move |sent, shop, _ctx| {
+                    if sent {
+                        return;
+                    }
+                    println!("No se pudo avisar la reposicion al ecom {}", ecom);
+                    // el ecom pudo haberse reconectado mientras tanto
+                    let current = shop.ecoms.get(&ecom);
+                    if current.is_some_and(|current| Arc::ptr_eq(current, &write)) {
+                        shop.ecoms.remove(&ecom);
+                    }
+                })
+                .spawn(ctx);
+        }
+        MessageResult(items)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix::Actor;
+    use futures::StreamExt;
+    use tokio::{
+        io::split,
+        net::{TcpListener, TcpStream},
+        sync::Mutex,
+    };
+
+    use super::*;
+    use crate::{
+        protocol::codec::framed_read,
+        shop::{read_inventory::ReadInventory, register_ecom::RegisterEcom, shop_actor::Product},
+    };
+
+    #[actix_rt::test]
+    async fn test_restock_adds_stock_and_reports_it() {
+        let shop = Shop::new(
+            "Tienda 1",
+            "",
+            1,
+            vec![Product {
+                id: "A".to_string(),
+                stock: 2,
+                reserved: 0,
+            }],
+        )
+        .start();
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let stream = TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let (ecom_side, _) = listener.accept().await.unwrap();
+        let (_read, write) = split(stream);
+        shop.send(RegisterEcom {
+            ecom: "ecom1".to_string(),
+            write: Arc::new(Mutex::new(write)),
+        })
+        .await
+        .unwrap();
+
+        let restocked = shop
+            .send(Restock {
+                items: vec![OrderItem::new("A", 5), OrderItem::new("Z", 3)],
+                reorder: false,
+            })
+            .await
+            .unwrap();
+        assert_eq!(restocked, vec![OrderItem::new("A", 5)]);
+
+        let stock = shop.send(ReadInventory).await.unwrap();
+        assert_eq!(stock[0].stock, 7);
+
+        let (read, _write) = split(ecom_side);
+        let message = framed_read(read).next().await.unwrap().unwrap();
+        assert_eq!(
+            message,
+            WireMessage::Restocked {
+                shop: "Tienda 1".to_string(),
+                items: vec![OrderItem::new("A", 5)],
+            }
+        );
+    }
+}
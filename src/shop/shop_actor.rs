@@ -1,26 +1,115 @@
+use crate::config::Config;
 use crate::error::FileError;
+use crate::order_id::OrderId;
+use crate::order_item::OrderItem;
 use crate::shop::local_purchase::LocalPurchase;
+use crate::shop::online_purchase::OnlinePurchase;
+use crate::shop::order_table::OrderTable;
+use crate::shop::restock::Restock;
+use crate::shop::shop_server_side::ShopIdentity;
+use crate::shop::stock_shard::StockShards;
+use crate::shop::supplier::Supplier;
 use crate::states::LocalPurchaseState;
-use actix::{Actor, Context};
+use crate::zones::ZoneMap;
+use actix::{Actor, ActorContext, Addr, AsyncContext, Context, SpawnHandle};
+use rand::{rngs::StdRng, SeedableRng};
 
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
 use std::fs::File;
+use std::hash::{Hash, Hasher};
 use std::io::{BufRead, BufReader, Read};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::WriteHalf;
+use tokio::net::TcpStream;
+use tokio::sync::Mutex;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Product {
     pub id: String,
     pub stock: u32,
     pub reserved: u32,
 }
 
+/// Stock below which the shop reorders a product from its supplier, and the quantity it
+/// asks for
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReorderPoint {
+    pub minimum: u32,
+    pub quantity: u32,
+}
+
+/// Online purchase reserved by the shop, waiting for the ecom to commit or abort it
+#[derive(Debug)]
+pub struct PreparedPurchase {
+    pub purchase: OnlinePurchase,
+    pub timeout: SpawnHandle,
+}
+
 pub struct Shop {
     pub name: String,
     pub address: String,
     pub location: u32,
+    /// Products of the shop until it starts, when they are handed to the stock shards
     pub stock: Vec<Product>,
+    /// Ids of the products handed to the stock shards
+    catalog: Vec<String>,
+    /// Shards that own the stock once the shop started
+    pub shards: StockShards,
+    /// Directory where the shards log the stock changes, if any
+    pub inventory_path: Option<String>,
+    pub zones: ZoneMap,
+    pub config: Config,
+    /// Random number generator for the processing and delivery times, and the deliveries
+    pub rng: StdRng,
+    /// Reserved purchases waiting for the ecom to commit or abort them
+    pub prepared_purchases: HashMap<OrderId, PreparedPurchase>,
+    /// Items of the committed purchases that are being delivered
+    pub delivering_purchases: HashMap<OrderId, Vec<OrderItem>>,
+    /// Online orders already processed, to answer the ones the ecoms send again
+    pub order_table: OrderTable,
+    /// Online purchases whose stock is being reserved
+    pub purchases_in_progress: HashSet<OrderId>,
+    /// Connections of the identified ecoms, to report the restocks to them
+    pub ecoms: HashMap<String, Arc<Mutex<WriteHalf<TcpStream>>>>,
+    /// Reorder point of each product that has one
+    pub reorder_points: HashMap<String, ReorderPoint>,
+    /// Products reordered whose restock did not arrive yet
+    pub reordering: HashSet<String>,
+    /// Restocks the supplier delivers on its own, handed to it when the shop starts
+    pub restocks: Vec<Restock>,
+    /// Supplier of the shop once it started
+    pub supplier: Option<Addr<Supplier>>,
 }
 
 impl Shop {
+    pub fn new(name: &str, address: &str, location: u32, stock: Vec<Product>) -> Self {
+        let config = Config::default();
+        let expiration = Duration::from_millis(config.order_table_expiration_millis);
+        Self {
+            name: name.to_string(),
+            address: address.to_string(),
+            location,
+            stock,
+            catalog: Vec::new(),
+            shards: StockShards::default(),
+            inventory_path: None,
+            zones: ZoneMap::default(),
+            config,
+            rng: StdRng::from_entropy(),
+            prepared_purchases: HashMap::new(),
+            delivering_purchases: HashMap::new(),
+            order_table: OrderTable::new(expiration),
+            purchases_in_progress: HashSet::new(),
+            ecoms: HashMap::new(),
+            reorder_points: HashMap::new(),
+            reordering: HashSet::new(),
+            restocks: Vec::new(),
+            supplier: None,
+        }
+    }
+
     /// Reads the shop info from the file in the given path
     pub fn from_file(path: &str) -> Result<Self, FileError> {
         let file = File::open(path).map_err(|_| FileError::NotFound)?;
@@ -54,12 +143,12 @@ impl Shop {
             return Err(FileError::WrongFormat);
         }
 
-        let mut shop = Self {
-            name: shop_info[0].to_string(),
-            address: shop_info[1].to_string(),
-            location: shop_info[2].parse().map_err(|_| FileError::WrongFormat)?,
-            stock: Vec::new(),
-        };
+        let mut shop = Self::new(
+            shop_info[0],
+            shop_info[1],
+            shop_info[2].parse().map_err(|_| FileError::WrongFormat)?,
+            Vec::new(),
+        );
 
         // ignore dash line
         lines.next();
@@ -69,15 +158,22 @@ impl Shop {
 
             let product_data: Vec<&str> = current_line.split(',').collect();
 
-            // ['KEY', 'VALUE'].len() == 2
-            if product_data.len() != 2 {
-                return Err(FileError::WrongFormat);
-            }
+            // ['KEY', 'VALUE'] o ['KEY', 'VALUE', 'MINIMUM', 'REORDER']
+            let (id, stock) = match product_data.as_slice() {
+                [id, stock] => (id, stock),
+                [id, stock, minimum, quantity] => {
+                    let point = ReorderPoint {
+                        minimum: minimum.parse().map_err(|_| FileError::WrongFormat)?,
+                        quantity: quantity.parse().map_err(|_| FileError::WrongFormat)?,
+                    };
+                    shop.reorder_points.insert(id.to_string(), point);
+                    (id, stock)
+                }
+                _ => return Err(FileError::WrongFormat),
+            };
             let product = Product {
-                id: product_data[0].to_string(),
-                stock: product_data[1]
-                    .parse()
-                    .map_err(|_| FileError::WrongFormat)?,
+                id: id.to_string(),
+                stock: stock.parse().map_err(|_| FileError::WrongFormat)?,
                 reserved: 0,
             };
 
@@ -87,6 +183,45 @@ impl Shop {
         Ok(shop)
     }
 
+    /// Returns a version of the catalog, which changes whenever the set of products changes
+    pub fn catalog_version(&self) -> u32 {
+        let mut products: Vec<&String> = self
+            .stock
+            .iter()
+            .map(|p| &p.id)
+            .chain(self.catalog.iter())
+            .collect();
+        products.sort();
+
+        let mut hasher = DefaultHasher::new();
+        products.hash(&mut hasher);
+        hasher.finish() as u32
+    }
+
+    /// Whether the product is in the catalog of the shop
+    pub fn sells(&self, product: &str) -> bool {
+        self.catalog.iter().any(|id| id == product)
+    }
+
+    /// Stores the current state of the purchase as the latest one sent to its ecom
+    pub fn record_state(&mut self, purchase: &OnlinePurchase) {
+        self.order_table.update_state(
+            &purchase.ecom,
+            &purchase.id,
+            purchase.state.clone(),
+            purchase.items.clone(),
+        );
+    }
+
+    /// Returns the identity the shop presents to the ecoms
+    pub fn identity(&self) -> ShopIdentity {
+        ShopIdentity {
+            name: self.name.clone(),
+            zone_id: self.location as i32,
+            catalog_version: self.catalog_version(),
+        }
+    }
+
     /// Reads the orders from the file in the given path
     pub fn orders_from_file(path: &str) -> Result<Vec<LocalPurchase>, FileError> {
         let file = File::open(path).map_err(|_| FileError::NotFound)?;
@@ -118,7 +253,65 @@ impl Shop {
 impl Actor for Shop {
     type Context = Context<Self>;
 
-    fn started(&mut self, _ctx: &mut Context<Self>) {
+    /// Hands the products to the stock shards and starts the supplier. If the inventory log
+    /// cannot be opened, the shop stops
+    fn started(&mut self, ctx: &mut Context<Self>) {
         println!("INICIANDO TIENDA [{:?}]", self.location);
+        // la configuracion se asigna luego de crear la tienda
+        let expiration = Duration::from_millis(self.config.order_table_expiration_millis);
+        self.order_table = OrderTable::new(expiration);
+        let products = std::mem::take(&mut self.stock);
+        self.catalog = products.iter().map(|p| p.id.clone()).collect();
+
+        let inventory = self
+            .inventory_path
+            .as_deref()
+            .map(|dir| (dir, self.name.as_str()));
+        // sin el registro del inventario los cambios se perderian, asi que la tienda no sigue
+        self.shards = match StockShards::start(products, inventory, &self.config) {
+            Ok(shards) => shards,
+            Err(error) => {
+                println!("ERROR abriendo inventario: {:?}", error);
+                ctx.stop();
+                return;
+            }
+        };
+
+        let restocks = std::mem::take(&mut self.restocks);
+        let supplier = Supplier::new(ctx.address().recipient(), restocks, &self.config);
+        self.supplier = Some(supplier.start());
+    }
+
+    /// Stops the arbiters of the stock shards, so their threads do not outlive the shop
+    fn stopped(&mut self, _ctx: &mut Context<Self>) {
+        self.shards.stop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shop_from_reader_with_reorder_points() {
+        let content = "retiro,localhost:2346,1\n\
+                       ----------------------\n\
+                       manzana,100,20,50\n\
+                       banana,200\n";
+
+        let shop = Shop::from_reader(content.as_bytes()).unwrap();
+
+        assert_eq!(shop.stock.len(), 2);
+        assert_eq!(
+            shop.reorder_points.get("manzana"),
+            Some(&ReorderPoint {
+                minimum: 20,
+                quantity: 50
+            })
+        );
+        assert!(!shop.reorder_points.contains_key("banana"));
+
+        let content = "retiro,localhost:2346,1\n---\nmanzana,100,20\n";
+        assert!(Shop::from_reader(content.as_bytes()).is_err());
     }
 }
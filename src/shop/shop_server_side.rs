@@ -1,40 +1,195 @@
 use std::{net::SocketAddr, sync::Arc};
 
-use actix::{Actor, ActorContext, Context, Recipient, StreamHandler};
+use actix::{
+    dev::{ContextFutureSpawner, ToEnvelope},
+    fut::wrap_future,
+    Actor, ActorContext, Addr, Context, Handler, Recipient, StreamHandler,
+};
 use colored::Colorize;
 use futures::TryFutureExt;
 use tokio::{
-    io::{split, AsyncBufReadExt, BufReader, WriteHalf},
+    io::{split, WriteHalf},
     net::{TcpListener, TcpStream},
     sync::Mutex,
 };
-use tokio_stream::wrappers;
 
-use crate::shop::online_purchase::OnlinePurchase;
+use crate::{
+    error::ProtocolError,
+    order_id::OrderId,
+    protocol::{
+        codec::{framed_read, send_message},
+        wire_message::WireMessage,
+    },
+    shop::{
+        abort_purchase::AbortPurchase, cancel_purchase::CancelPurchase,
+        commit_purchase::CommitPurchase, online_purchase::OnlinePurchase,
+        register_ecom::RegisterEcom,
+    },
+    states::OnlinePurchaseState,
+};
+
+/// Recipients of the shop actor for the messages that arrive from the ecoms
+#[derive(Clone)]
+pub struct ShopRecipients {
+    pub purchase: Recipient<OnlinePurchase>,
+    pub commit: Recipient<CommitPurchase>,
+    pub abort: Recipient<AbortPurchase>,
+    pub cancel: Recipient<CancelPurchase>,
+    pub register: Recipient<RegisterEcom>,
+}
+
+impl ShopRecipients {
+    pub fn new<A>(shop: &Addr<A>) -> Self
+    where
+        A: Handler<OnlinePurchase>
+            + Handler<CommitPurchase>
+            + Handler<AbortPurchase>
+            + Handler<CancelPurchase>
+            + Handler<RegisterEcom>,
+        A::Context: ToEnvelope<A, OnlinePurchase>
+            + ToEnvelope<A, CommitPurchase>
+            + ToEnvelope<A, AbortPurchase>
+            + ToEnvelope<A, CancelPurchase>
+            + ToEnvelope<A, RegisterEcom>,
+    {
+        Self {
+            purchase: shop.clone().recipient(),
+            commit: shop.clone().recipient(),
+            abort: shop.clone().recipient(),
+            cancel: shop.clone().recipient(),
+            register: shop.clone().recipient(),
+        }
+    }
+}
+
+/// Identity of the shop, sent to every ecom that says Hello
+#[derive(Debug, Clone)]
+pub struct ShopIdentity {
+    pub name: String,
+    pub zone_id: i32,
+    pub catalog_version: u32,
+}
 
 pub struct ShopServerSide {
     pub write: Arc<Mutex<WriteHalf<TcpStream>>>,
     pub addr: SocketAddr,
-    pub shop_recipient: Recipient<OnlinePurchase>, //Lo vamos a usar para mandar msg al actor
+    pub shop: ShopRecipients, //Lo vamos a usar para mandar msg al actor
+    pub identity: ShopIdentity,
+    pub ecom: Option<String>, // Nombre del ecom, conocido luego del Hello
 }
 
 impl Actor for ShopServerSide {
     type Context = Context<Self>;
 }
 
-impl StreamHandler<Result<String, std::io::Error>> for ShopServerSide {
-    /// Handles the messages received from the ecom and sends them to the shop actor
-    /// to be processed as an order
-    fn handle(&mut self, read: Result<String, std::io::Error>, _ctx: &mut Self::Context) {
-        let ecom = self.addr.port().to_string();
-        if let Ok(line) = read {
-            let order = line.split(',').collect::<Vec<&str>>();
-            let purchase = match OnlinePurchase::parse(order, ecom, self.write.clone()) {
-                Ok(purchase) => purchase,
-                Err(_) => return,
-            };
-
-            self.shop_recipient.do_send(purchase);
+impl ShopServerSide {
+    /// Name used in the logs for the connected ecom
+    fn ecom_label(&self) -> String {
+        match &self.ecom {
+            Some(ecom) => ecom.clone(),
+            None => self.addr.port().to_string(),
+        }
+    }
+
+    /// Sends the given message to the ecom
+    fn send(&self, message: WireMessage, ctx: &mut Context<Self>) {
+        let write = self.write.clone();
+        wrap_future::<_, Self>(async move {
+            if send_message(&write, message).await.is_err() {
+                println!("Error al enviar mensaje");
+            }
+        })
+        .wait(ctx);
+    }
+
+    /// Registers the name of the ecom, also in the shop so it gets the restocks, and answers
+    /// with the identity of the shop
+    fn handle_hello(&mut self, ecom: String, ctx: &mut Context<Self>) {
+        println!(
+            "{}",
+            format!("[ECOM {}] Identificado como {}", self.addr.port(), ecom).purple()
+        );
+        self.shop.register.do_send(RegisterEcom {
+            ecom: ecom.clone(),
+            write: self.write.clone(),
+        });
+        self.ecom = Some(ecom);
+        self.send(
+            WireMessage::Welcome {
+                shop: self.identity.name.clone(),
+                zone_id: self.identity.zone_id,
+                catalog_version: self.identity.catalog_version,
+            },
+            ctx,
+        );
+    }
+
+    /// Sends the order to the shop actor, or rejects it if the ecom did not identify itself
+    fn handle_order(&mut self, message: WireMessage, ctx: &mut Context<Self>) {
+        let ecom = match &self.ecom {
+            Some(ecom) => ecom.clone(),
+            None => {
+                println!(
+                    "{}",
+                    format!("[ECOM {}] Pedido sin identificacion", self.addr.port()).purple()
+                );
+                if let WireMessage::Prepare { id, items, .. } = message {
+                    self.send(
+                        WireMessage::OrderState {
+                            id,
+                            state: OnlinePurchaseState::REJECTED,
+                            items,
+                        },
+                        ctx,
+                    );
+                }
+                return;
+            }
+        };
+
+        let purchase = match OnlinePurchase::parse(message, ecom, self.write.clone()) {
+            Ok(purchase) => purchase,
+            Err(_) => return,
+        };
+
+        self.shop.purchase.do_send(purchase);
+    }
+
+    /// Sends the cancellation of the order to the shop actor, which answers the ecom directly
+    fn handle_cancel(&mut self, id: OrderId) {
+        self.shop.cancel.do_send(CancelPurchase {
+            id,
+            ecom: self.ecom_label(),
+            write: self.write.clone(),
+        });
+    }
+}
+
+impl StreamHandler<Result<WireMessage, ProtocolError>> for ShopServerSide {
+    /// Handles the messages received from the ecom: the Hello identifies the ecom, each Ping
+    /// gets a Pong back, and the
+    /// prepares, commits, aborts and cancellations are sent to the shop actor to be processed
+    fn handle(&mut self, read: Result<WireMessage, ProtocolError>, ctx: &mut Self::Context) {
+        match read {
+            Ok(WireMessage::Hello { ecom }) => self.handle_hello(ecom, ctx),
+            Ok(WireMessage::Ping) => self.send(WireMessage::Pong, ctx),
+            Ok(message @ WireMessage::Prepare { .. }) => self.handle_order(message, ctx),
+            Ok(WireMessage::Commit { id }) => self.shop.commit.do_send(CommitPurchase { id }),
+            Ok(WireMessage::Abort { id }) => self.shop.abort.do_send(AbortPurchase { id }),
+            Ok(WireMessage::Cancel { id }) => self.handle_cancel(id),
+            Ok(message) => println!(
+                "{}",
+                format!(
+                    "[ECOM {}] Mensaje inesperado: {:?}",
+                    self.ecom_label(),
+                    message
+                )
+                .purple()
+            ),
+            Err(error) => println!(
+                "{}",
+                format!("[ECOM {}] Mensaje invalido: {:?}", self.ecom_label(), error).purple()
+            ),
         }
     }
 
@@ -42,7 +197,7 @@ impl StreamHandler<Result<String, std::io::Error>> for ShopServerSide {
     fn finished(&mut self, ctx: &mut Self::Context) {
         println!(
             "{}",
-            format!("[ECOM {:?}] Desconectado", self.addr.port()).purple()
+            format!("[ECOM {}] Desconectado", self.ecom_label()).purple()
         );
         ctx.stop();
     }
@@ -51,32 +206,43 @@ impl StreamHandler<Result<String, std::io::Error>> for ShopServerSide {
 /// Creates a server that listens for connections from the ecommerce and sends the messages to the
 /// shop server side actor, which will process them and send them to the shop actor
 pub async fn initiate_shop_server_side(
-    shop_recipient: Recipient<OnlinePurchase>,
+    shop: ShopRecipients,
+    identity: ShopIdentity,
     address: String,
 ) -> Result<(), String> {
     let listener = TcpListener::bind(address.as_str())
         .map_err(|_| String::from("Error listening port"))
         .await?;
 
+    serve_shop_server_side(shop, identity, listener).await
+}
+
+/// Accepts connections from the ecommerces in the given listener, already bound, and creates
+/// a shop server side actor for each one
+pub async fn serve_shop_server_side(
+    shop: ShopRecipients,
+    identity: ShopIdentity,
+    listener: TcpListener,
+) -> Result<(), String> {
     while let Ok((stream, addr)) = listener.accept().await {
         println!(
             "{}",
             format!("[ECOM] Se conectó el Ecommerce {:?}", addr.port()).purple()
         );
-        let shop_recipient = shop_recipient.clone();
+        let shop = shop.clone();
+        let identity = identity.clone();
         ShopServerSide::create(|ctx| {
             let (read, write_half) = split(stream);
-            ShopServerSide::add_stream(
-                wrappers::LinesStream::new(BufReader::new(read).lines()),
-                ctx,
-            );
+            ShopServerSide::add_stream(framed_read(read), ctx);
 
             let write = Arc::new(Mutex::new(write_half));
 
             ShopServerSide {
                 addr,
                 write,
-                shop_recipient,
+                shop,
+                identity,
+                ecom: None,
             }
         });
     }
@@ -0,0 +1,146 @@
+use std::collections::HashMap;
+
+use super::{inventory_log::StockChange, shop_actor::Product};
+
+/// Totals of the stock taken from a product since its shard started. They are wider than the
+/// stock, so a long running shop does not overflow them
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ProductLedger {
+    /// Units the product had when the shard started, reserved ones included
+    pub initial: u64,
+    pub sold: u64,
+    pub delivered: u64,
+    /// Units whose delivery got lost, given back to the stock
+    pub lost: u64,
+    /// Units delivered by the suppliers
+    pub restocked: u64,
+}
+
+impl ProductLedger {
+    /// Whether every unit the product started with or got restocked is in stock, reserved,
+    /// sold or delivered
+    pub fn balances(&self, product: &Product) -> bool {
+        u64::from(product.stock) + u64::from(product.reserved) + self.sold + self.delivered
+            == self.initial + self.restocked
+    }
+}
+
+/// Accounting of the stock changes of the products of a shard, to check that no unit is
+/// created or lost along the way
+#[derive(Debug, Default)]
+pub struct StockLedger {
+    products: HashMap<String, ProductLedger>,
+}
+
+impl StockLedger {
+    pub fn new(products: &[Product]) -> Self {
+        let products = products
+            .iter()
+            .map(|product| {
+                let ledger = ProductLedger {
+                    initial: u64::from(product.stock) + u64::from(product.reserved),
+                    ..ProductLedger::default()
+                };
+                (product.id.clone(), ledger)
+            })
+            .collect();
+        Self { products }
+    }
+
+    /// Registers a change already applied to the stock of the product
+    pub fn record(&mut self, product: &str, change: &StockChange, quantity: u32) {
+        let ledger = match self.products.get_mut(product) {
+            Some(ledger) => ledger,
+            None => return,
+        };
+        let quantity = u64::from(quantity);
+        match change {
+            StockChange::Sold => ledger.sold += quantity,
+            StockChange::Delivered => ledger.delivered += quantity,
+            StockChange::Returned => ledger.lost += quantity,
+            StockChange::Restocked => ledger.restocked += quantity,
+            StockChange::Reserved | StockChange::Released => {}
+        }
+    }
+
+    /// Ledger of the product, if it belongs to the shard
+    pub fn get(&self, product: &str) -> Option<&ProductLedger> {
+        self.products.get(product)
+    }
+
+    /// Checks that the ledger of every product balances with its stock. Returns the first
+    /// product that does not
+    pub fn check(&self, products: &[Product]) -> Result<(), String> {
+        for product in products {
+            let balances = self
+                .products
+                .get(&product.id)
+                .is_some_and(|ledger| ledger.balances(product));
+            if !balances {
+                return Err(product.id.clone());
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    fn change() -> impl Strategy<Value = StockChange> {
+        prop_oneof![
+            Just(StockChange::Sold),
+            Just(StockChange::Reserved),
+            Just(StockChange::Released),
+            Just(StockChange::Delivered),
+            Just(StockChange::Returned),
+            Just(StockChange::Restocked),
+        ]
+    }
+
+    #[test]
+    fn test_ledger_totals_outgrow_the_stock() {
+        let product = Product {
+            id: "A".to_string(),
+            stock: u32::MAX,
+            reserved: 0,
+        };
+        let mut ledger = StockLedger::new(&[product]);
+
+        ledger.record("A", &StockChange::Restocked, u32::MAX);
+        ledger.record("A", &StockChange::Restocked, u32::MAX);
+        assert_eq!(ledger.get("A").unwrap().restocked, 2 * u64::from(u32::MAX));
+    }
+
+    proptest! {
+        /// Whatever the changes and their order, the ones that can be applied keep the
+        /// ledger balanced and the rest leave the product as it was
+        #[test]
+        fn test_ledger_balances_after_any_changes(
+            initial in 0u32..50,
+            changes in prop::collection::vec((change(), 0u32..20), 0..60),
+        ) {
+            let mut product = Product {
+                id: "A".to_string(),
+                stock: initial,
+                reserved: 0,
+            };
+            let mut ledger = StockLedger::new(&[product.clone()]);
+
+            for (change, quantity) in changes {
+                let before = product.clone();
+                match change.apply(&mut product, quantity) {
+                    Ok(()) => ledger.record("A", &change, quantity),
+                    Err(_) => {
+                        prop_assert_eq!(product.stock, before.stock);
+                        prop_assert_eq!(product.reserved, before.reserved);
+                    }
+                }
+                prop_assert!(ledger.check(&[product.clone()]).is_ok());
+            }
+        }
+    }
+}
@@ -0,0 +1,474 @@
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+    time::Duration,
+};
+
+use actix::{Actor, Addr, Arbiter, ArbiterHandle, AsyncContext, Context, Handler, Message};
+use futures::future::join_all;
+
+use crate::{
+    config::Config,
+    error::{FileError, StockError},
+    order_item::OrderItem,
+    shop::shop_actor::Product,
+};
+
+use super::{
+    inventory_log::{InventoryLog, StockChange},
+    stock_ledger::{ProductLedger, StockLedger},
+};
+
+/// Portion of the stock of a shop: the products whose id hashes to it. Each shard runs in
+/// its own arbiter, so purchases of products in different shards are processed in parallel.
+pub struct StockShard {
+    products: Vec<Product>,
+    /// Log where the stock changes of the shard are written, if any
+    log: Option<InventoryLog>,
+    /// Totals of the stock sold, delivered and lost of each product
+    ledger: StockLedger,
+    /// Time between the snapshots of the stock, if it has a log
+    snapshot_interval: Duration,
+}
+
+impl StockShard {
+    pub fn new(
+        products: Vec<Product>,
+        log: Option<InventoryLog>,
+        snapshot_interval: Duration,
+    ) -> Self {
+        let ledger = StockLedger::new(&products);
+        Self {
+            products,
+            log,
+            ledger,
+            snapshot_interval,
+        }
+    }
+
+    /// Applies the change to the stock of the product, writing it first to the log. Changes
+    /// that would take more units than the product has, or that cannot be written to the
+    /// log, are refused and leave the stock as it was
+    fn change(
+        &mut self,
+        change: &StockChange,
+        product: &str,
+        quantity: u32,
+    ) -> Result<(), StockError> {
+        let product = self
+            .products
+            .iter_mut()
+            .find(|p| p.id == product)
+            .ok_or(StockError::UnknownProduct)?;
+        let mut changed = product.clone();
+        if let Err(error) = change.apply(&mut changed, quantity) {
+            println!(
+                "ERROR de inventario: {:?} {} x {} ({:?})",
+                change, quantity, product.id, error
+            );
+            return Err(error);
+        }
+        if let Some(log) = self.log.as_mut() {
+            if log.append(change, &product.id, quantity).is_err() {
+                // sin el registro, el cambio se perderia al reiniciar la tienda
+                println!(
+                    "Error al escribir el inventario, se rechaza: {:?} {} x {}",
+                    change, quantity, product.id
+                );
+                return Err(StockError::NotLogged);
+            }
+        }
+        *product = changed;
+        self.ledger.record(&product.id, change, quantity);
+        Ok(())
+    }
+
+    /// Checks, in debug builds, that the ledger of every product balances with its stock,
+    /// reporting the products that do not. The shard keeps working either way
+    fn check_ledger(&self) {
+        #[cfg(debug_assertions)]
+        if let Err(product) = self.ledger.check(&self.products) {
+            println!(
+                "ERROR inventario inconsistente para el producto {}",
+                product
+            );
+        }
+    }
+
+    /// Quantity of the product that can be taken from the stock
+    fn available(&self, product: &str) -> u32 {
+        self.products
+            .iter()
+            .find(|p| p.id == product)
+            .map_or(0, |p| p.stock)
+    }
+}
+
+impl Actor for StockShard {
+    type Context = Context<Self>;
+
+    /// Saves a snapshot of the stock periodically, if it changed since the last one
+    fn started(&mut self, ctx: &mut Context<Self>) {
+        ctx.run_interval(self.snapshot_interval, |shard, _ctx| {
+            if let Some(log) = shard.log.as_mut() {
+                if log.has_unsaved() && log.snapshot(&shard.products).is_err() {
+                    println!("Error al guardar el inventario");
+                }
+            }
+        });
+    }
+}
+
+/// Takes the items from the stock with the given change (sold or reserved). Returns the
+/// quantity taken of each item, in the same order. Items whose change is refused, as when it
+/// cannot be logged, are not taken
+#[derive(Debug, Message)]
+#[rtype(result = "Vec<OrderItem>")]
+pub struct TakeStock {
+    pub items: Vec<OrderItem>,
+    pub change: StockChange,
+    /// Whether each item may take only what is available. Otherwise every item gets taken
+    /// or none
+    pub allow_partial: bool,
+}
+
+impl Handler<TakeStock> for StockShard {
+    type Result = Vec<OrderItem>;
+
+    fn handle(&mut self, msg: TakeStock, _ctx: &mut Context<Self>) -> Self::Result {
+        let mut taken: Vec<OrderItem> = msg
+            .items
+            .iter()
+            .map(|item| {
+                let available = self.available(&item.product);
+                OrderItem::new(&item.product, item.quantity.min(available))
+            })
+            .collect();
+
+        let complete = msg
+            .items
+            .iter()
+            .all(|item| self.products.iter().any(|p| p.id == item.product))
+            && taken == msg.items;
+        if !msg.allow_partial && !complete {
+            taken.iter_mut().for_each(|item| item.quantity = 0);
+        }
+
+        // los cambios rechazados no toman nada; sin reservas parciales, quien pidio el stock
+        // libera el resto
+        for item in taken.iter_mut().filter(|item| item.quantity > 0) {
+            if self
+                .change(&msg.change, &item.product, item.quantity)
+                .is_err()
+            {
+                item.quantity = 0;
+            }
+        }
+        self.check_ledger();
+        taken
+    }
+}
+
+/// Applies the change (released, delivered or returned) to the items of a reservation
+#[derive(Debug, Message)]
+#[rtype(result = "()")]
+pub struct ApplyStock {
+    pub items: Vec<OrderItem>,
+    pub change: StockChange,
+}
+
+impl Handler<ApplyStock> for StockShard {
+    type Result = ();
+
+    fn handle(&mut self, msg: ApplyStock, _ctx: &mut Context<Self>) -> Self::Result {
+        for item in &msg.items {
+            // el error ya se informo y el stock quedo como estaba
+            let _ = self.change(&msg.change, &item.product, item.quantity);
+        }
+        self.check_ledger();
+    }
+}
+
+/// Returns the products of the shard
+#[derive(Debug, Message)]
+#[rtype(result = "Vec<Product>")]
+pub struct ReadStock;
+
+impl Handler<ReadStock> for StockShard {
+    type Result = Vec<Product>;
+
+    fn handle(&mut self, _msg: ReadStock, _ctx: &mut Context<Self>) -> Self::Result {
+        self.products.clone()
+    }
+}
+
+/// Returns the products of the shard along with their ledgers
+#[derive(Debug, Message)]
+#[rtype(result = "Vec<(Product, ProductLedger)>")]
+pub struct ReadShardLedger;
+
+impl Handler<ReadShardLedger> for StockShard {
+    type Result = Vec<(Product, ProductLedger)>;
+
+    fn handle(&mut self, _msg: ReadShardLedger, _ctx: &mut Context<Self>) -> Self::Result {
+        self.products
+            .iter()
+            .map(|product| {
+                let ledger = self.ledger.get(&product.id).cloned().unwrap_or_default();
+                (product.clone(), ledger)
+            })
+            .collect()
+    }
+}
+
+/// Shards that own the stock of a shop once it started
+#[derive(Debug, Clone, Default)]
+pub struct StockShards {
+    shards: Vec<Addr<StockShard>>,
+    /// Arbiters where the shards run, stopped along with the shop
+    arbiters: Vec<ArbiterHandle>,
+}
+
+impl StockShards {
+    /// Splits the products among the number of shards of the configuration, each one started
+    /// in a new arbiter. If a directory is given, each shard logs its changes in it and saves
+    /// snapshots of its stock with the interval of the configuration.
+    pub fn start(
+        products: Vec<Product>,
+        inventory: Option<(&str, &str)>,
+        config: &Config,
+    ) -> Result<Self, FileError> {
+        let count = config.stock_shards.max(1);
+        let snapshot_interval = Duration::from_millis(config.inventory_snapshot_millis);
+        let mut split: Vec<Vec<Product>> = (0..count).map(|_| Vec::new()).collect();
+        for product in products {
+            split[Self::shard_of(&product.id, count)].push(product);
+        }
+
+        // todos los logs se crean antes de arrancar los shards, asi un error no deja
+        // arbiters andando
+        let mut logs = Vec::new();
+        for (index, products) in split.iter().enumerate() {
+            logs.push(match inventory {
+                Some((dir, shop)) => Some(InventoryLog::create(dir, shop, index, products)?),
+                None => None,
+            });
+        }
+        // los archivos de los shards que sobran se borran una vez que los nuevos snapshots
+        // tienen sus productos
+        if let Some((dir, shop)) = inventory {
+            InventoryLog::remove_from(dir, shop, count);
+        }
+
+        let mut shards = Vec::new();
+        let mut arbiters = Vec::new();
+        for (products, log) in split.into_iter().zip(logs) {
+            let shard = StockShard::new(products, log, snapshot_interval);
+            let arbiter = Arbiter::new().handle();
+            shards.push(StockShard::start_in_arbiter(&arbiter, move |_| shard));
+            arbiters.push(arbiter);
+        }
+        Ok(Self { shards, arbiters })
+    }
+
+    /// Stops the arbiters of the shards, which ends their threads
+    pub fn stop(&self) {
+        for arbiter in &self.arbiters {
+            arbiter.stop();
+        }
+    }
+
+    /// Index of the shard that owns the product, among the given number of shards
+    pub fn shard_of(product: &str, count: usize) -> usize {
+        let mut hasher = DefaultHasher::new();
+        product.hash(&mut hasher);
+        (hasher.finish() % count as u64) as usize
+    }
+
+    /// Items grouped by the shard that owns them, with their position in the given items
+    fn split(&self, items: &[OrderItem]) -> Vec<(usize, Vec<(usize, OrderItem)>)> {
+        let mut split: Vec<(usize, Vec<(usize, OrderItem)>)> = Vec::new();
+        if self.shards.is_empty() {
+            return split; // la tienda no empezo
+        }
+        for (position, item) in items.iter().enumerate() {
+            let shard = Self::shard_of(&item.product, self.shards.len());
+            match split.iter_mut().find(|(index, _)| *index == shard) {
+                Some((_, shard_items)) => shard_items.push((position, item.clone())),
+                None => split.push((shard, vec![(position, item.clone())])),
+            }
+        }
+        split
+    }
+
+    /// Takes the items from the shards that own them with the given change. Returns the
+    /// quantity taken of each item, in the same order. Without partial reservations, if a
+    /// shard cannot take its items, the ones taken by the others are released.
+    /// Only reservations may span several shards, as sales cannot be released.
+    pub async fn take(
+        &self,
+        items: Vec<OrderItem>,
+        change: StockChange,
+        allow_partial: bool,
+    ) -> Vec<OrderItem> {
+        let split = self.split(&items);
+        let requests = split.iter().map(|(shard, shard_items)| {
+            self.shards[*shard].send(TakeStock {
+                items: shard_items.iter().map(|(_, item)| item.clone()).collect(),
+                change: change.clone(),
+                allow_partial,
+            })
+        });
+        let answers = join_all(requests).await;
+
+        let mut taken: Vec<OrderItem> = items
+            .iter()
+            .map(|item| OrderItem::new(&item.product, 0))
+            .collect();
+        for ((_, shard_items), answer) in split.iter().zip(answers) {
+            for ((position, _), item) in shard_items.iter().zip(answer.unwrap_or_default()) {
+                taken[*position] = item;
+            }
+        }
+
+        if !allow_partial && taken != items {
+            let reserved: Vec<OrderItem> = taken
+                .iter()
+                .filter(|item| item.quantity > 0)
+                .cloned()
+                .collect();
+            self.apply(reserved, StockChange::Released);
+            taken.iter_mut().for_each(|item| item.quantity = 0);
+        }
+        taken
+    }
+
+    /// Applies the change to the items in the shards that own them
+    pub fn apply(&self, items: Vec<OrderItem>, change: StockChange) {
+        for (shard, shard_items) in self.split(&items) {
+            self.shards[shard].do_send(ApplyStock {
+                items: shard_items.into_iter().map(|(_, item)| item).collect(),
+                change: change.clone(),
+            });
+        }
+    }
+
+    /// Returns the products of every shard
+    pub async fn read(&self) -> Vec<Product> {
+        let answers = join_all(self.shards.iter().map(|shard| shard.send(ReadStock))).await;
+        answers
+            .into_iter()
+            .flat_map(Result::unwrap_or_default)
+            .collect()
+    }
+
+    /// Returns the products of every shard along with their ledgers
+    pub async fn ledger(&self) -> Vec<(Product, ProductLedger)> {
+        let answers = join_all(self.shards.iter().map(|shard| shard.send(ReadShardLedger))).await;
+        answers
+            .into_iter()
+            .flat_map(Result::unwrap_or_default)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::clock;
+
+    use super::*;
+
+    fn product(id: &str, stock: u32) -> Product {
+        Product {
+            id: id.to_string(),
+            stock,
+            reserved: 0,
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_take_releases_other_shards_if_one_fails() {
+        let products = vec![product("A", 5), product("B", 5), product("C", 5)];
+        let config = Config {
+            stock_shards: 3,
+            ..Config::default()
+        };
+        let shards = StockShards::start(products, None, &config).unwrap();
+        let items = vec![
+            OrderItem::new("A", 2),
+            OrderItem::new("B", 2),
+            OrderItem::new("C", 8),
+        ];
+
+        let taken = shards
+            .take(items.clone(), StockChange::Reserved, false)
+            .await;
+        assert!(taken.iter().all(|item| item.quantity == 0));
+
+        let taken = shards.take(items, StockChange::Reserved, true).await;
+        assert_eq!(
+            taken,
+            vec![
+                OrderItem::new("A", 2),
+                OrderItem::new("B", 2),
+                OrderItem::new("C", 5),
+            ]
+        );
+
+        let mut stock = shards.read().await;
+        stock.sort_by(|a, b| a.id.cmp(&b.id));
+        let stock: Vec<(u32, u32)> = stock.iter().map(|p| (p.stock, p.reserved)).collect();
+        assert_eq!(stock, vec![(3, 2), (3, 2), (0, 5)]);
+
+        shards.stop();
+        clock::sleep(Duration::from_millis(50)).await;
+        assert!(shards.read().await.is_empty());
+    }
+
+    #[actix_rt::test]
+    async fn test_changes_not_logged_are_refused() {
+        let dir = std::env::temp_dir().join(format!("inventario-shard-{}", std::process::id()));
+        let dir = dir.to_string_lossy().to_string();
+        let products = vec![product("A", 5)];
+        let mut log = InventoryLog::create(&dir, "tienda", 0, &products).unwrap();
+        log.break_log();
+        let shard = StockShard::new(products, Some(log), Duration::from_secs(60)).start();
+
+        let taken = shard
+            .send(TakeStock {
+                items: vec![OrderItem::new("A", 2)],
+                change: StockChange::Sold,
+                allow_partial: false,
+            })
+            .await
+            .unwrap();
+        assert_eq!(taken, vec![OrderItem::new("A", 0)]);
+
+        let stock = shard.send(ReadStock).await.unwrap();
+        assert_eq!((stock[0].stock, stock[0].reserved), (5, 0));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[actix_rt::test]
+    async fn test_fewer_shards_keep_every_product() {
+        let dir = std::env::temp_dir().join(format!("inventario-menos-{}", std::process::id()));
+        let dir = dir.to_string_lossy().to_string();
+        let products = vec![product("A", 5), product("B", 4), product("C", 3)];
+        let config = |stock_shards| Config {
+            stock_shards,
+            ..Config::default()
+        };
+
+        let shards = StockShards::start(products, Some((&dir, "tienda")), &config(3)).unwrap();
+        shards.stop();
+        let restored = InventoryLog::restore(&dir, "tienda").unwrap().unwrap();
+        let shards = StockShards::start(restored, Some((&dir, "tienda")), &config(1)).unwrap();
+        shards.stop();
+
+        let mut restored = InventoryLog::restore(&dir, "tienda").unwrap().unwrap();
+        restored.sort_by(|a, b| a.id.cmp(&b.id));
+        let stock: Vec<(&str, u32)> = restored.iter().map(|p| (p.id.as_str(), p.stock)).collect();
+        assert_eq!(stock, vec![("A", 5), ("B", 4), ("C", 3)]);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,170 @@
+use std::{
+    collections::VecDeque,
+    fs::File,
+    io::{BufRead, BufReader, Read},
+    ops::RangeInclusive,
+    time::Duration,
+};
+
+use actix::{
+    Actor, ActorFutureExt, AsyncContext, Context, Handler, Message, Recipient, ResponseActFuture,
+    WrapFuture,
+};
+use rand::{rngs::StdRng, Rng};
+
+use crate::{
+    clock,
+    config::Config,
+    constants::SUPPLIER_SALT,
+    error::FileError,
+    order_item::{describe, OrderItem},
+};
+
+use super::restock::Restock;
+
+/// Simulates the supplier of a shop: delivers its scheduled restocks one after another,
+/// each one after a random time in `supplier_millis`, and the ones the shop asks for when
+/// a product runs low
+pub struct Supplier {
+    shop: Recipient<Restock>,
+    restocks: VecDeque<Restock>,
+    millis: RangeInclusive<u64>,
+    rng: StdRng,
+}
+
+impl Supplier {
+    pub fn new(shop: Recipient<Restock>, restocks: Vec<Restock>, config: &Config) -> Self {
+        Self {
+            shop,
+            restocks: restocks.into(),
+            millis: config.supplier_millis.clone(),
+            rng: config.salted_rng(SUPPLIER_SALT),
+        }
+    }
+
+    /// Reads the restocks from the file in the given path
+    pub fn restocks_from_file(path: &str) -> Result<Vec<Restock>, FileError> {
+        let file = File::open(path).map_err(|_| FileError::NotFound)?;
+        Self::restocks_from_reader(file)
+    }
+
+    /// Reads the restocks from the given reader, one `<product>,<quantity>` per line. A
+    /// line with several pairs, separated by `;`, is a single restock of all of them
+    fn restocks_from_reader<T: Read>(content: T) -> Result<Vec<Restock>, FileError> {
+        let reader = BufReader::new(content);
+        let mut restocks = Vec::new();
+
+        for line in reader.lines() {
+            let current_line = line.map_err(|_| FileError::WrongFormat)?;
+            let items = current_line
+                .split(';')
+                .map(
+                    |item| match item.split(',').collect::<Vec<&str>>().as_slice() {
+                        [product, quantity] => Ok(OrderItem::new(
+                            product.trim(),
+                            quantity
+                                .trim()
+                                .parse()
+                                .map_err(|_| FileError::WrongFormat)?,
+                        )),
+                        _ => Err(FileError::WrongFormat),
+                    },
+                )
+                .collect::<Result<Vec<OrderItem>, FileError>>()?;
+            restocks.push(Restock {
+                items,
+                reorder: false,
+            });
+        }
+
+        Ok(restocks)
+    }
+}
+
+impl Actor for Supplier {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Context<Self>) {
+        ctx.notify(NextRestock);
+    }
+}
+
+#[derive(Debug, Message)]
+#[rtype(result = "()")]
+struct NextRestock;
+
+impl Handler<NextRestock> for Supplier {
+    type Result = ResponseActFuture<Self, ()>;
+
+    /// Waits for the next scheduled restock and delivers it to the shop
+    fn handle(&mut self, _msg: NextRestock, _ctx: &mut Context<Self>) -> Self::Result {
+        let restock = match self.restocks.pop_front() {
+            Some(restock) => restock,
+            None => return Box::pin(actix::fut::ready(())),
+        };
+        let millis = self.rng.gen_range(self.millis.clone());
+
+        Box::pin(
+            clock::sleep(Duration::from_millis(millis))
+                .into_actor(self)
+                .map(move |_, supplier, ctx| {
+                    supplier.shop.do_send(restock);
+                    if supplier.restocks.is_empty() {
+                        println!("[PROVEEDOR] Entregadas las reposiciones programadas");
+                    }
+                    ctx.notify(NextRestock);
+                }),
+        )
+    }
+}
+
+/// Restock the shop asks for when a product runs low
+#[derive(Debug, Message)]
+#[rtype(result = "()")]
+pub struct RequestRestock {
+    pub items: Vec<OrderItem>,
+}
+
+impl Handler<RequestRestock> for Supplier {
+    type Result = ResponseActFuture<Self, ()>;
+
+    /// Delivers the items to the shop after a random time in `supplier_millis`, without
+    /// delaying the scheduled restocks
+    fn handle(&mut self, msg: RequestRestock, _ctx: &mut Context<Self>) -> Self::Result {
+        println!("[PROVEEDOR] Pedido de reposicion: {}", describe(&msg.items));
+        let millis = self.rng.gen_range(self.millis.clone());
+
+        Box::pin(
+            clock::sleep(Duration::from_millis(millis))
+                .into_actor(self)
+                .map(move |_, supplier, _ctx| {
+                    supplier.shop.do_send(Restock {
+                        items: msg.items,
+                        reorder: true,
+                    });
+                }),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_restocks_from_reader() {
+        let content = "manzana,10\nbanana,5;pelota,2\n";
+
+        let restocks = Supplier::restocks_from_reader(content.as_bytes()).unwrap();
+
+        assert_eq!(restocks.len(), 2);
+        assert_eq!(restocks[0].items, vec![OrderItem::new("manzana", 10)]);
+        assert_eq!(
+            restocks[1].items,
+            vec![OrderItem::new("banana", 5), OrderItem::new("pelota", 2)]
+        );
+
+        assert!(Supplier::restocks_from_reader("manzana".as_bytes()).is_err());
+        assert!(Supplier::restocks_from_reader("manzana,diez".as_bytes()).is_err());
+    }
+}
@@ -1,7 +1,5 @@
 use colored::Colorize;
-use rand::{thread_rng, Rng};
-
-use crate::constants::DELIVER_RATE;
+use rand::Rng;
 
 //LocalPurchase solo va a tener 3 estados: CREATED, SOLD o REJECTED.
 #[derive(Debug, Clone, PartialEq)]
@@ -20,27 +18,36 @@ impl LocalPurchaseState {
     }
 }
 
-//EcomPurchase va a tener 5 estados: CREATED, RESERVED, DELIVERED, REJECTED o LOST.
+//EcomPurchase va a tener 8 estados: CREATED, RESERVED, PARTIAL, DELIVERED, REJECTED, LOST,
+//CANCELLED o TOO_LATE.
 #[derive(Debug, Clone)]
+#[allow(non_camel_case_types)]
 pub enum OnlinePurchaseState {
     RECEIVED,
     RESERVED,
+    PARTIAL,
     DELIVERED,
     REJECTED,
     LOST,
+    CANCELLED,
+    TOO_LATE,
 }
 impl OnlinePurchaseState {
     pub fn string_to_print(&self) -> String {
         match self {
             OnlinePurchaseState::RECEIVED => "RECIBIDO".to_string(),
             OnlinePurchaseState::RESERVED => "RESERVADO".yellow().to_string(),
+            OnlinePurchaseState::PARTIAL => "PARCIAL".yellow().to_string(),
             OnlinePurchaseState::REJECTED => "NO STOCK".red().to_string(),
             OnlinePurchaseState::DELIVERED => "ENTREGADO".green().to_string(),
             OnlinePurchaseState::LOST => "PERDIDO".red().to_string(),
+            OnlinePurchaseState::CANCELLED => "CANCELADO".yellow().to_string(),
+            OnlinePurchaseState::TOO_LATE => "DEMASIADO TARDE".red().to_string(),
         }
     }
-    pub fn deliver_attempt(&mut self) {
-        let is_delivered = thread_rng().gen_bool(DELIVER_RATE);
+    /// Tries to deliver the purchase, which reaches the customer with the given probability
+    pub fn deliver_attempt<R: Rng>(&mut self, rng: &mut R, deliver_rate: f64) {
+        let is_delivered = rng.gen_bool(deliver_rate);
         if is_delivered {
             *self = OnlinePurchaseState::DELIVERED;
         } else {
@@ -54,6 +61,9 @@ impl OnlinePurchaseState {
             2 => Some(OnlinePurchaseState::REJECTED),
             3 => Some(OnlinePurchaseState::DELIVERED),
             4 => Some(OnlinePurchaseState::LOST),
+            5 => Some(OnlinePurchaseState::PARTIAL),
+            6 => Some(OnlinePurchaseState::CANCELLED),
+            7 => Some(OnlinePurchaseState::TOO_LATE),
             _ => None,
         }
     }
@@ -64,6 +74,9 @@ impl OnlinePurchaseState {
             OnlinePurchaseState::REJECTED => 2,
             OnlinePurchaseState::DELIVERED => 3,
             OnlinePurchaseState::LOST => 4,
+            OnlinePurchaseState::PARTIAL => 5,
+            OnlinePurchaseState::CANCELLED => 6,
+            OnlinePurchaseState::TOO_LATE => 7,
         }
     }
 }
@@ -73,3 +86,38 @@ impl PartialEq for OnlinePurchaseState {
         self.string_to_print() == other.string_to_print()
     }
 }
+
+//Una tienda conectada al ecom va a tener 3 estados: ALIVE, SUSPECT o DOWN.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ShopStatus {
+    ALIVE,
+    SUSPECT,
+    DOWN,
+}
+impl ShopStatus {
+    pub fn string_to_print(&self) -> String {
+        match self {
+            ShopStatus::ALIVE => "ACTIVA".green().to_string(),
+            ShopStatus::SUSPECT => "SOSPECHOSA".yellow().to_string(),
+            ShopStatus::DOWN => "CAIDA".red().to_string(),
+        }
+    }
+}
+
+//El circuit breaker de una tienda en el ecom va a tener 3 estados: CLOSED, OPEN o HALF_OPEN.
+#[allow(non_camel_case_types)]
+#[derive(Debug, Clone, PartialEq)]
+pub enum BreakerState {
+    CLOSED,
+    OPEN,
+    HALF_OPEN,
+}
+impl BreakerState {
+    pub fn string_to_print(&self) -> String {
+        match self {
+            BreakerState::CLOSED => "CERRADO".green().to_string(),
+            BreakerState::OPEN => "ABIERTO".red().to_string(),
+            BreakerState::HALF_OPEN => "SEMIABIERTO".yellow().to_string(),
+        }
+    }
+}
@@ -0,0 +1,161 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+
+use crate::error::{FileError, ZoneError};
+
+/// Map of the zones as a graph, where each road between two zones has a travel cost.
+/// Without roads, the distance between zones is the difference between their ids.
+#[derive(Debug, Clone, Default)]
+pub struct ZoneMap {
+    roads: HashMap<i32, Vec<(i32, u32)>>,
+}
+
+impl ZoneMap {
+    /// Reads the zone map from the file in the given path
+    pub fn from_file(path: &str) -> Result<Self, FileError> {
+        let file = File::open(path).map_err(|_| FileError::NotFound)?;
+        Self::from_reader(file)
+    }
+
+    /// Reads the zone map from the file in the given path, if it exists.
+    /// Without it, zones are compared by their ids.
+    pub fn from_optional_file(path: &str) -> Result<Self, FileError> {
+        match Self::from_file(path) {
+            Err(FileError::NotFound) => Ok(Self::default()),
+            result => result,
+        }
+    }
+
+    /// Reads the zone map from the given reader, with one road per line:
+    /// `<zone_a>,<zone_b>,<cost>`. Roads can be travelled in both directions.
+    pub fn from_reader<T: Read>(content: T) -> Result<Self, FileError> {
+        let reader = BufReader::new(content);
+        let mut zones = Self::default();
+
+        for line in reader.lines() {
+            let current_line = line.map_err(|_| FileError::WrongFormat)?;
+            if current_line.trim().is_empty() {
+                continue;
+            }
+
+            let road: Vec<&str> = current_line.split(',').collect();
+            // ['ZONE_A', 'ZONE_B', 'COST'].len() == 3
+            if road.len() != 3 {
+                return Err(FileError::WrongFormat);
+            }
+            let from: i32 = road[0].parse().map_err(|_| FileError::WrongFormat)?;
+            let to: i32 = road[1].parse().map_err(|_| FileError::WrongFormat)?;
+            let cost: u32 = road[2].parse().map_err(|_| FileError::WrongFormat)?;
+
+            zones.add_road(from, to, cost);
+        }
+
+        Ok(zones)
+    }
+
+    pub fn add_road(&mut self, from: i32, to: i32, cost: u32) {
+        self.roads.entry(from).or_default().push((to, cost));
+        self.roads.entry(to).or_default().push((from, cost));
+    }
+
+    /// Returns the cost of the shortest path between the given zones.
+    /// If the map has no roads, the difference between the zone ids is used instead.
+    /// Returns None if there is no path between the zones.
+    pub fn distance(&self, from: i32, to: i32) -> Option<u32> {
+        if self.roads.is_empty() {
+            return Some(from.abs_diff(to));
+        }
+        if from == to {
+            return Some(0);
+        }
+
+        let mut best: HashMap<i32, u32> = HashMap::from([(from, 0)]);
+        let mut queue = BinaryHeap::from([Reverse((0, from))]);
+
+        while let Some(Reverse((cost, zone))) = queue.pop() {
+            if zone == to {
+                return Some(cost);
+            }
+            if cost > *best.get(&zone).unwrap_or(&u32::MAX) {
+                continue;
+            }
+            for (next, road_cost) in self.roads.get(&zone).into_iter().flatten() {
+                let next_cost = cost.saturating_add(*road_cost);
+                if next_cost < *best.get(next).unwrap_or(&u32::MAX) {
+                    best.insert(*next, next_cost);
+                    queue.push(Reverse((next_cost, *next)));
+                }
+            }
+        }
+
+        None
+    }
+
+    /// Checks that every shop can deliver to every order zone: all the zones are in the map
+    /// and there is a path from each shop zone to each order zone. Without roads, any zone
+    /// is covered. Otherwise a zone missing from the map would get all its deliveries lost
+    pub fn check_coverage(&self, shop_zones: &[i32], order_zones: &[i32]) -> Result<(), ZoneError> {
+        if self.roads.is_empty() {
+            return Ok(());
+        }
+        for zone in shop_zones.iter().chain(order_zones) {
+            if !self.roads.contains_key(zone) {
+                return Err(ZoneError::MissingZone(*zone));
+            }
+        }
+        for shop in shop_zones {
+            for order in order_zones {
+                if self.distance(*shop, *order).is_none() {
+                    return Err(ZoneError::Unreachable(*shop, *order));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_distance_without_roads() {
+        let zones = ZoneMap::default();
+
+        assert_eq!(zones.distance(1, 5), Some(4));
+        assert_eq!(zones.distance(5, 1), Some(4));
+    }
+
+    #[test]
+    fn test_shortest_path_distance() {
+        let zones = ZoneMap::from_reader("1,2,10\n2,3,1\n1,3,4\n3,4,2\n".as_bytes()).unwrap();
+
+        assert_eq!(zones.distance(1, 1), Some(0));
+        assert_eq!(zones.distance(1, 2), Some(5));
+        assert_eq!(zones.distance(4, 1), Some(6));
+        assert_eq!(zones.distance(1, 7), None);
+    }
+
+    #[test]
+    fn test_check_coverage() {
+        let zones = ZoneMap::from_reader("1,2,10\n2,3,1\n5,6,1\n".as_bytes()).unwrap();
+
+        assert_eq!(zones.check_coverage(&[1, 3], &[1, 2, 3]), Ok(()));
+        assert_eq!(
+            zones.check_coverage(&[1], &[4]),
+            Err(ZoneError::MissingZone(4))
+        );
+        assert_eq!(
+            zones.check_coverage(&[1], &[2, 6]),
+            Err(ZoneError::Unreachable(1, 6))
+        );
+        assert_eq!(ZoneMap::default().check_coverage(&[1], &[9]), Ok(()));
+    }
+
+    #[test]
+    fn test_wrong_format() {
+        assert!(ZoneMap::from_reader("1,2\n".as_bytes()).is_err());
+    }
+}
@@ -0,0 +1,107 @@
+mod cluster;
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use concurrentes::{
+        chaos::fault_plan::{FaultPlan, FrameFaults, Partition},
+        config::Config,
+    };
+
+    use crate::cluster::{delivered, Cluster, ShopSpec};
+
+    const SHOPS: [ShopSpec; 2] = [
+        ShopSpec {
+            name: "retiro",
+            zone_id: 1,
+            stock: &[("A", 20), ("B", 10)],
+        },
+        ShopSpec {
+            name: "palermo",
+            zone_id: 5,
+            stock: &[("A", 20), ("B", 10)],
+        },
+    ];
+
+    /// Orders for the ecoms: `(product, quantity, zone)`
+    const ORDERS: [(&str, u32, i32); 8] = [
+        ("A", 3, 1),
+        ("B", 2, 5),
+        ("A", 4, 5),
+        ("B", 3, 1),
+        ("A", 2, 1),
+        ("A", 5, 5),
+        ("B", 1, 1),
+        ("A", 1, 5),
+    ];
+
+    /// Short timeouts, so the orders lost by the faults are retried quickly
+    fn config() -> Config {
+        Config {
+            ecom_max_waiting_millis: 300,
+            prepare_timeout_millis: 600,
+            ..Cluster::config()
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_orders_survive_duplicated_delayed_and_reordered_frames() {
+        let faults = FrameFaults {
+            duplicate_rate: 0.2,
+            delay_rate: 0.2,
+            reorder_rate: 0.2,
+            delay_millis: 5..=50,
+            ..FrameFaults::default()
+        };
+        let plan = FaultPlan::symmetric(faults, 1);
+        let cluster = Cluster::start_with_faults(&SHOPS, &["ecom1"], &config(), &plan).await;
+
+        let ids = cluster.place_orders(0, &ORDERS);
+        let orders = cluster.wait_for_orders(0, ORDERS.len()).await;
+
+        // sin perdidas hay stock para todos los pedidos
+        for id in ids.iter() {
+            assert!(delivered(&orders, id), "pedido {} no entregado", id);
+        }
+        cluster.assert_consistent(&SHOPS, &[orders]).await;
+    }
+
+    #[actix_rt::test]
+    async fn test_orders_survive_dropped_and_truncated_frames() {
+        let faults = FrameFaults {
+            drop_rate: 0.1,
+            truncate_rate: 0.05,
+            ..FrameFaults::default()
+        };
+        let plan = FaultPlan::symmetric(faults, 2);
+        let cluster =
+            Cluster::start_with_faults(&SHOPS, &["ecom1", "ecom2"], &config(), &plan).await;
+
+        cluster.place_orders(0, &ORDERS);
+        cluster.place_orders(1, &ORDERS);
+        let first = cluster.wait_for_orders(0, ORDERS.len()).await;
+        let second = cluster.wait_for_orders(1, ORDERS.len()).await;
+
+        cluster.assert_consistent(&SHOPS, &[first, second]).await;
+    }
+
+    #[actix_rt::test]
+    async fn test_orders_survive_a_partition() {
+        let plan = FaultPlan {
+            partitions: vec![Partition {
+                start: Duration::from_millis(50),
+                duration: Duration::from_millis(1000),
+            }],
+            ..FaultPlan::default()
+        };
+        let cluster = Cluster::start_with_faults(&SHOPS, &["ecom1"], &config(), &plan).await;
+
+        let ids = cluster.place_orders(0, &ORDERS);
+        let orders = cluster.wait_for_orders(0, ORDERS.len()).await;
+
+        // cada pedido termina, entregado por la tienda que quedo o luego de reconectar
+        assert_eq!(orders.finished.len(), ids.len());
+        cluster.assert_consistent(&SHOPS, &[orders]).await;
+    }
+}
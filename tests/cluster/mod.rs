@@ -0,0 +1,319 @@
+//! In-process cluster of shops and ecoms for the integration tests. Every node runs as an
+//! actor in the System of the test, and the shops listen in ephemeral ports, so tests can
+//! run in parallel without sharing ports. The shops can be placed behind fault-injection
+//! proxies, to check that the orders survive network faults.
+//!
+//! Each test crate that declares `mod cluster` uses only part of it.
+#![allow(dead_code)]
+
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use actix::{Actor, Addr};
+use concurrentes::{
+    chaos::{fault_plan::FaultPlan, fault_proxy::FaultProxy},
+    clock,
+    config::Config,
+    ecom::{
+        connect_shops::ConnectShops,
+        ecom_actor::{Ecom, EcomOrder},
+        process_ecom_orders::ProcessEcomOrders,
+        read_orders::{EcomOrders, ReadOrders},
+    },
+    order_id::OrderId,
+    order_item::{quantity_of, OrderItem},
+    shop::{
+        read_inventory::ReadInventory,
+        shop_actor::{Product, Shop},
+        shop_server_side::{serve_shop_server_side, ShopRecipients},
+    },
+};
+use tokio::net::TcpListener;
+
+/// Time the cluster has to reach the state expected by an assertion
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Time between checks of the state of the cluster
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Shop to start in the cluster
+pub struct ShopSpec<'a> {
+    pub name: &'a str,
+    pub zone_id: u32,
+    pub stock: &'a [(&'a str, u32)],
+}
+
+pub struct ClusterShop {
+    pub zone_id: i32,
+    /// Address the ecoms connect to: the one of its proxy, if any
+    pub address: String,
+    pub actor: Addr<Shop>,
+    pub proxy: Option<FaultProxy>,
+}
+
+pub struct ClusterEcom {
+    pub name: String,
+    pub actor: Addr<Ecom>,
+}
+
+pub struct Cluster {
+    pub shops: Vec<ClusterShop>,
+    pub ecoms: Vec<ClusterEcom>,
+}
+
+impl Cluster {
+    /// Configuration with short times and every delivery reaching the customer, so the
+    /// outcome of the orders only depends on the stock
+    pub fn config() -> Config {
+        Config {
+            purchase_millis: 10,
+            local_procesing_millis: 1..=5,
+            deliver_millis: 10..=20,
+            deliver_millis_per_distance: 1,
+            ecom_procesing_millis: 1..=5,
+            ecom_max_waiting_millis: 2000,
+            deliver_rate: 1.0,
+            seed: Some(0),
+            ..Config::default()
+        }
+    }
+
+    /// Starts the given shops, each one listening in an ephemeral port, and an ecom with
+    /// each of the given names connected to all of them
+    pub async fn start(shops: &[ShopSpec<'_>], ecoms: &[&str], config: &Config) -> Self {
+        Self::launch(shops, ecoms, config, None).await
+    }
+
+    /// Same as `start`, but the ecoms reach each shop through its own proxy applying the
+    /// given faults. Each proxy draws its faults from a different seed
+    pub async fn start_with_faults(
+        shops: &[ShopSpec<'_>],
+        ecoms: &[&str],
+        config: &Config,
+        faults: &FaultPlan,
+    ) -> Self {
+        Self::launch(shops, ecoms, config, Some(faults)).await
+    }
+
+    async fn launch(
+        shops: &[ShopSpec<'_>],
+        ecoms: &[&str],
+        config: &Config,
+        faults: Option<&FaultPlan>,
+    ) -> Self {
+        let mut cluster_shops = Vec::new();
+        for (index, spec) in shops.iter().enumerate() {
+            let listener = TcpListener::bind("localhost:0").await.unwrap();
+            let address = listener.local_addr().unwrap().to_string();
+
+            let stock = spec
+                .stock
+                .iter()
+                .map(|(id, stock)| Product {
+                    id: id.to_string(),
+                    stock: *stock,
+                    reserved: 0,
+                })
+                .collect();
+            let mut shop = Shop::new(spec.name, &address, spec.zone_id, stock);
+            shop.rng = config.rng();
+            shop.config = config.clone();
+            let identity = shop.identity();
+            let actor = shop.start();
+
+            let recipients = ShopRecipients::new(&actor);
+            actix::spawn(async move {
+                let _ = serve_shop_server_side(recipients, identity, listener).await;
+            });
+
+            let proxy = match faults {
+                Some(faults) => {
+                    let plan = FaultPlan {
+                        seed: faults.seed + index as u64,
+                        ..faults.clone()
+                    };
+                    Some(
+                        FaultProxy::start("localhost:0", &address, plan)
+                            .await
+                            .unwrap(),
+                    )
+                }
+                None => None,
+            };
+            cluster_shops.push(ClusterShop {
+                zone_id: spec.zone_id as i32,
+                address: proxy
+                    .as_ref()
+                    .map_or(address, |proxy| proxy.address.clone()),
+                actor,
+                proxy,
+            });
+        }
+
+        let addresses: Vec<(String, i32, String)> = shops
+            .iter()
+            .zip(cluster_shops.iter())
+            .map(|(spec, shop)| (spec.name.to_string(), shop.zone_id, shop.address.clone()))
+            .collect();
+        let mut cluster_ecoms = Vec::new();
+        for name in ecoms {
+            let mut ecom = Ecom::new(name);
+            ecom.addresses = Some(addresses.clone());
+            ecom.rng = config.rng();
+            ecom.config = config.clone();
+            let ecom = ecom.start();
+            ecom.send(ConnectShops).await.unwrap().unwrap();
+            cluster_ecoms.push(ClusterEcom {
+                name: name.to_string(),
+                actor: ecom,
+            });
+        }
+
+        Self {
+            shops: cluster_shops,
+            ecoms: cluster_ecoms,
+        }
+    }
+
+    /// Actor of the shop in the given zone
+    pub fn shop(&self, zone_id: i32) -> &Addr<Shop> {
+        &self
+            .shops
+            .iter()
+            .find(|shop| shop.zone_id == zone_id)
+            .expect("no shop in that zone")
+            .actor
+    }
+
+    /// Sends the given orders to the ecom with the given index, each one with a single item:
+    /// `(product, quantity, zone)`. Returns the ids of the orders, in the same order
+    pub fn place_orders(&self, ecom: usize, orders: &[(&str, u32, i32)]) -> Vec<OrderId> {
+        let name = &self.ecoms[ecom].name;
+        let orders: Vec<EcomOrder> = orders
+            .iter()
+            .enumerate()
+            .map(|(index, (product, quantity, zone_id))| {
+                EcomOrder::new(
+                    OrderId::new(name, index as u32 + 1),
+                    vec![OrderItem::new(product, *quantity)],
+                    *zone_id,
+                )
+            })
+            .collect();
+        let ids = orders.iter().map(|order| order.id.clone()).collect();
+        self.ecoms[ecom].actor.do_send(ProcessEcomOrders(orders));
+        ids
+    }
+
+    /// Waits until the ecom with the given index finished the given amount of orders and has
+    /// none pending, and returns its orders
+    pub async fn wait_for_orders(&self, ecom: usize, finished: usize) -> EcomOrders {
+        let start = Instant::now();
+        loop {
+            let orders = self.ecoms[ecom].actor.send(ReadOrders).await.unwrap();
+            if orders.pending.is_empty() && orders.finished.len() >= finished {
+                return orders;
+            }
+            assert!(
+                start.elapsed() < TIMEOUT,
+                "ecom {} did not finish its orders: {:?}",
+                ecom,
+                orders
+            );
+            clock::sleep(POLL_INTERVAL).await;
+        }
+    }
+
+    /// Asserts that the stock and reserved quantity of the product in the shop of the given
+    /// zone get to the given ones, waiting for the changes still in flight
+    pub async fn assert_stock(&self, zone_id: i32, product: &str, stock: u32, reserved: u32) {
+        let start = Instant::now();
+        loop {
+            let inventory = self.shop(zone_id).send(ReadInventory).await.unwrap();
+            let current = inventory
+                .iter()
+                .find(|p| p.id == product)
+                .map(|p| (p.stock, p.reserved));
+            if current == Some((stock, reserved)) {
+                return;
+            }
+            assert!(
+                start.elapsed() < TIMEOUT,
+                "shop {} has {:?} of {} instead of {:?}",
+                zone_id,
+                current,
+                product,
+                (stock, reserved)
+            );
+            clock::sleep(POLL_INTERVAL).await;
+        }
+    }
+
+    /// Asserts that no stock was lost or delivered twice: once every reservation is released,
+    /// the stock taken from the shops is the one delivered to the orders of the ecoms, and no
+    /// order got more than it asked for
+    pub async fn assert_consistent(&self, shops: &[ShopSpec<'_>], ecoms: &[EcomOrders]) {
+        let mut initial: HashMap<String, u32> = HashMap::new();
+        for spec in shops {
+            for (product, stock) in spec.stock {
+                *initial.entry(product.to_string()).or_default() += stock;
+            }
+        }
+        let mut delivered: HashMap<String, u32> = HashMap::new();
+        for order in ecoms.iter().flat_map(|orders| orders.finished.iter()) {
+            for item in order.items.iter() {
+                let quantity = quantity_of(&order.delivered, &item.product);
+                assert!(
+                    quantity <= item.quantity,
+                    "pedido {} entregado de mas: {:?}",
+                    order.id,
+                    order.delivered
+                );
+                *delivered.entry(item.product.clone()).or_default() += quantity;
+            }
+        }
+
+        let start = Instant::now();
+        loop {
+            let mut remaining: HashMap<String, u32> = HashMap::new();
+            let mut reserved = 0;
+            for shop in self.shops.iter() {
+                for product in shop.actor.send(ReadInventory).await.unwrap() {
+                    *remaining.entry(product.id).or_default() += product.stock;
+                    reserved += product.reserved;
+                }
+            }
+            let taken: HashMap<String, u32> = initial
+                .iter()
+                .map(|(product, stock)| (product.clone(), stock - remaining[product]))
+                .filter(|(_, taken)| *taken > 0)
+                .collect();
+            let delivered: HashMap<String, u32> = delivered
+                .iter()
+                .filter(|(_, quantity)| **quantity > 0)
+                .map(|(product, quantity)| (product.clone(), *quantity))
+                .collect();
+            if reserved == 0 && taken == delivered {
+                return;
+            }
+            assert!(
+                start.elapsed() < TIMEOUT,
+                "se tomaron {:?} con {} reservados, pero se entregaron {:?}",
+                taken,
+                reserved,
+                delivered
+            );
+            clock::sleep(POLL_INTERVAL).await;
+        }
+    }
+}
+
+/// Whether the order with the given id was delivered
+pub fn delivered(orders: &EcomOrders, id: &OrderId) -> bool {
+    orders
+        .finished
+        .iter()
+        .any(|order| order.id == *id && order.is_delivered())
+}
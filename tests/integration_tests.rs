@@ -1,65 +1,94 @@
+mod cluster;
+
 #[cfg(test)]
 mod tests {
     use std::{
-        io::{BufRead, BufReader},
+        io::Read,
         sync::{mpsc, Arc},
         thread,
         time::Duration,
     };
 
-    use actix::{actors::mocker::Mocker, Actor, Recipient};
+    use actix::{actors::mocker::Mocker, Actor};
+    use actix_codec::{Decoder, Encoder};
     use actix_rt::System;
+    use bytes::BytesMut;
     use concurrentes::{
         ecom::ecom_actor::EcomOrder,
+        order_id::OrderId,
+        order_item::OrderItem,
+        protocol::{codec::WireCodec, wire_message::WireMessage},
         shop::{
+            commit_purchase::CommitPurchase,
             local_purchase::LocalPurchase,
             online_purchase::OnlinePurchase,
+            register_ecom::RegisterEcom,
             shop_actor::{Product, Shop},
-            shop_server_side::initiate_shop_server_side,
+            shop_server_side::{serve_shop_server_side, ShopIdentity, ShopRecipients},
         },
         states::{LocalPurchaseState, OnlinePurchaseState},
     };
-    use tokio::{io::AsyncWriteExt, net::TcpStream, sync::Mutex};
+    use rand::{rngs::StdRng, SeedableRng};
+    use tokio::{
+        io::AsyncWriteExt,
+        net::{TcpListener, TcpStream},
+        sync::Mutex,
+    };
 
-    #[actix_rt::test]
-    async fn test_online_purchase_delivered_or_lost() {
+    use crate::cluster::{delivered, Cluster, ShopSpec};
+
+    /// Reads a single frame from the given stream and decodes it
+    fn read_message(stream: &mut std::net::TcpStream) -> WireMessage {
+        let mut length = [0u8; 4];
+        stream.read_exact(&mut length).unwrap();
+        let mut frame = vec![0u8; u32::from_be_bytes(length) as usize];
+        stream.read_exact(&mut frame).unwrap();
+
+        let mut buffer = BytesMut::from(&length[..]);
+        buffer.extend_from_slice(&frame);
+        WireCodec::default().decode(&mut buffer).unwrap().unwrap()
+    }
+
+    /// Reserves and commits an online purchase of 5 out of 10 in a shop seeded with the given
+    /// seed, then sells 2 locally until the stock runs out. Returns the state the shop sent
+    /// after delivering the online purchase, and the state of the last local purchase.
+    async fn deliver_with_seed(seed: u64) -> (OnlinePurchaseState, LocalPurchaseState) {
         let (tx, rx) = mpsc::channel();
+        let listener = std::net::TcpListener::bind("localhost:0").unwrap();
+        let address = listener.local_addr().unwrap();
         thread::spawn(move || {
-            let listener = std::net::TcpListener::bind("localhost:8765").unwrap();
-            for a in listener.incoming() {
-                let stream = a.unwrap();
-                let mut reader = BufReader::new(stream);
-                let mut line = String::new();
-                reader.read_line(&mut line).unwrap();
-                tx.send(line).unwrap();
+            let (mut stream, _) = listener.accept().unwrap();
+            loop {
+                tx.send(read_message(&mut stream)).unwrap();
             }
         });
 
-        let shop = Shop {
-            name: "Tienda 1".to_string(),
-            address: "".to_string(),
-            location: 1,
-            stock: vec![Product {
+        let mut shop = Shop::new(
+            "Tienda 1",
+            "",
+            1,
+            vec![Product {
                 id: "A".to_string(),
                 stock: 10,
                 reserved: 0,
             }],
-        }
-        .start();
+        );
+        shop.rng = StdRng::seed_from_u64(seed);
+        let shop = shop.start();
 
         thread::sleep(Duration::from_millis(100));
 
-        let stream = std::net::TcpStream::connect("localhost:8765").unwrap();
+        let stream = std::net::TcpStream::connect(address).unwrap();
         let (_read, write) = tokio::io::split(TcpStream::from_std(stream).unwrap());
 
         let online_purchase = OnlinePurchase {
-            id: 1,
+            id: OrderId::new("1", 1),
             ecom: "1".to_string(),
-            product: "A".to_string(),
-            quantity: 5,
+            items: vec![OrderItem::new("A", 5)],
             state: OnlinePurchaseState::RECEIVED,
             zone_id: 1,
             write: Arc::new(Mutex::new(write)),
+            allow_partial: false,
         };
         let middle_purchases = LocalPurchase {
             product: "A".to_string(),
@@ -70,73 +99,164 @@ mod tests {
         let result = shop.send(online_purchase).await.unwrap().unwrap();
 
         assert_eq!(result, OnlinePurchaseState::RESERVED);
+        shop.send(CommitPurchase {
+            id: OrderId::new("1", 1),
+        })
+        .await
+        .unwrap();
         let _ = shop.send(middle_purchases.clone()).await.unwrap();
-        thread::sleep(Duration::from_millis(1100));
+        // la entrega termina mientras el reloj avanza, sin esperarla de verdad
+        tokio::time::sleep(Duration::from_millis(1100)).await;
         let _ = shop.send(middle_purchases.clone()).await.unwrap();
         let _ = shop.send(middle_purchases.clone()).await.unwrap();
         let last_purchase = shop.send(middle_purchases.clone()).await.unwrap().unwrap();
 
-        let mut received = rx.recv().unwrap();
-        received.truncate(received.len() - 1);
+        let mut states = Vec::new();
+        for _ in 0..2 {
+            match rx.recv().unwrap() {
+                WireMessage::OrderState { id, state, items } => {
+                    assert_eq!(id, OrderId::new("1", 1));
+                    assert_eq!(items, vec![OrderItem::new("A", 5)]);
+                    states.push(state);
+                }
+                message => panic!("unexpected message {:?}", message),
+            }
+        }
+        assert_eq!(states[0], OnlinePurchaseState::RESERVED);
+        (states[1].clone(), last_purchase)
+    }
 
-        let order_str = received.split(',').collect::<Vec<&str>>();
-        let state_number: u8 = order_str[1].parse().unwrap();
-        let state = OnlinePurchaseState::from_int(state_number).unwrap();
+    #[actix_rt::test]
+    async fn test_online_purchase_outcome_depends_on_seed() {
+        tokio::time::pause();
 
-        assert!(state == OnlinePurchaseState::DELIVERED || state == OnlinePurchaseState::LOST);
-        if state == OnlinePurchaseState::DELIVERED {
-            assert_eq!(last_purchase, LocalPurchaseState::REJECTED);
-        } else {
-            assert_eq!(last_purchase, LocalPurchaseState::SOLD);
-        }
+        // con la semilla 1 el pedido se entrega y el stock se agota
+        let (state, last_purchase) = deliver_with_seed(1).await;
+        assert_eq!(state, OnlinePurchaseState::DELIVERED);
+        assert_eq!(last_purchase, LocalPurchaseState::REJECTED);
+
+        // con la semilla 8 se pierde y el stock vuelve a la tienda
+        let (state, last_purchase) = deliver_with_seed(8).await;
+        assert_eq!(state, OnlinePurchaseState::LOST);
+        assert_eq!(last_purchase, LocalPurchaseState::SOLD);
     }
 
     #[actix_rt::test]
     async fn test_connected_shop_receive_order() {
         let (tx, rx) = mpsc::channel();
+        let (address_tx, address_rx) = mpsc::channel();
 
         thread::spawn(|| {
             let system = System::new();
-            system.block_on(async {
-                let shop_mocker: Recipient<OnlinePurchase> =
-                    Mocker::<OnlinePurchase>::mock(Box::new(move |msg, _ctx| {
-                        let purchase = msg.downcast_ref::<OnlinePurchase>().unwrap().clone();
-                        tx.send(purchase).unwrap();
-                        msg
-                    }))
-                    .start()
-                    .recipient();
-
-                initiate_shop_server_side(shop_mocker, "localhost:8766".to_string())
+            system.block_on(async move {
+                let shop_mocker = Mocker::<OnlinePurchase>::mock(Box::new(move |msg, _ctx| {
+                    // el ecom tambien se registra en la tienda al identificarse
+                    if msg.is::<RegisterEcom>() {
+                        return Box::new(Some(()));
+                    }
+                    let purchase = msg.downcast_ref::<OnlinePurchase>().unwrap().clone();
+                    tx.send(purchase).unwrap();
+                    msg
+                }))
+                .start();
+
+                let identity = ShopIdentity {
+                    name: "retiro".to_string(),
+                    zone_id: 1,
+                    catalog_version: 1,
+                };
+                let listener = TcpListener::bind("localhost:0").await.unwrap();
+                address_tx.send(listener.local_addr().unwrap()).unwrap();
+                serve_shop_server_side(ShopRecipients::new(&shop_mocker), identity, listener)
                     .await
                     .unwrap();
             });
             system.run().unwrap();
         });
 
-        thread::sleep(Duration::from_millis(1000));
-
-        let stream = std::net::TcpStream::connect("localhost:8766").unwrap();
+        let stream = std::net::TcpStream::connect(address_rx.recv().unwrap()).unwrap();
         let (_read, mut write) = tokio::io::split(TcpStream::from_std(stream).unwrap());
 
-        let ecom_order = EcomOrder {
-            id: 1,
-            quantity: 1,
-            product_id: "A".to_string(),
-            shops_requested: vec![1],
-            zone_id: 1,
-        };
+        let mut ecom_order =
+            EcomOrder::new(OrderId::new("ecom1", 1), vec![OrderItem::new("A", 1)], 1);
+        ecom_order.shops_requested = vec![1];
 
-        write
-            .write_all(ecom_order.as_string().as_bytes())
-            .await
+        let mut frame = BytesMut::new();
+        let mut codec = WireCodec::default();
+        codec
+            .encode(
+                WireMessage::Hello {
+                    ecom: "ecom1".to_string(),
+                },
+                &mut frame,
+            )
             .unwrap();
+        codec.encode(ecom_order.to_message(), &mut frame).unwrap();
+        write.write_all(&frame).await.unwrap();
 
         let received = rx.recv().unwrap();
-        assert!(received.id == 1);
-        assert!(received.product == "A".to_string());
-        assert!(received.quantity == 1);
+        assert!(received.id == OrderId::new("ecom1", 1));
+        assert!(received.ecom == "ecom1");
+        assert_eq!(received.items, vec![OrderItem::new("A", 1)]);
         assert!(received.zone_id == 1);
         assert!(received.state == OnlinePurchaseState::RECEIVED);
     }
+
+    #[actix_rt::test]
+    async fn test_cluster_routes_orders_by_stock() {
+        let cluster = Cluster::start(
+            &[
+                ShopSpec {
+                    name: "retiro",
+                    zone_id: 1,
+                    stock: &[("A", 5), ("B", 2)],
+                },
+                ShopSpec {
+                    name: "palermo",
+                    zone_id: 5,
+                    stock: &[("A", 10)],
+                },
+            ],
+            &["ecom1"],
+            &Cluster::config(),
+        )
+        .await;
+
+        // el segundo pedido no entra en retiro, y nadie tiene 5 de B
+        let ids = cluster.place_orders(0, &[("A", 3, 1), ("A", 4, 1), ("B", 5, 1)]);
+        let orders = cluster.wait_for_orders(0, 3).await;
+
+        assert!(delivered(&orders, &ids[0]));
+        assert!(delivered(&orders, &ids[1]));
+        assert!(!delivered(&orders, &ids[2]));
+        cluster.assert_stock(1, "A", 2, 0).await;
+        cluster.assert_stock(1, "B", 2, 0).await;
+        cluster.assert_stock(5, "A", 6, 0).await;
+    }
+
+    #[actix_rt::test]
+    async fn test_cluster_ecoms_compete_for_stock() {
+        let cluster = Cluster::start(
+            &[ShopSpec {
+                name: "retiro",
+                zone_id: 1,
+                stock: &[("A", 5)],
+            }],
+            &["ecom1", "ecom2"],
+            &Cluster::config(),
+        )
+        .await;
+
+        let first = cluster.place_orders(0, &[("A", 3, 1)]);
+        let second = cluster.place_orders(1, &[("A", 3, 1)]);
+        let first_orders = cluster.wait_for_orders(0, 1).await;
+        let second_orders = cluster.wait_for_orders(1, 1).await;
+
+        // solo uno de los dos pedidos encuentra stock
+        assert_ne!(
+            delivered(&first_orders, &first[0]),
+            delivered(&second_orders, &second[0])
+        );
+        cluster.assert_stock(1, "A", 2, 0).await;
+    }
 }
@@ -0,0 +1,335 @@
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        io::Read,
+        sync::{self as std_sync, Arc},
+        thread,
+        time::{Duration, Instant},
+    };
+
+    use actix::{Actor, Addr, System};
+    use actix_codec::Decoder;
+    use bytes::BytesMut;
+    use concurrentes::{
+        clock,
+        config::Config,
+        order_id::OrderId,
+        order_item::OrderItem,
+        protocol::{codec::WireCodec, wire_message::WireMessage},
+        shop::{
+            abort_purchase::AbortPurchase,
+            cancel_purchase::CancelPurchase,
+            commit_purchase::CommitPurchase,
+            local_purchase::LocalPurchase,
+            online_purchase::OnlinePurchase,
+            read_ledger::ReadLedger,
+            restock::Restock,
+            shop_actor::{Product, Shop},
+            stock_ledger::ProductLedger,
+        },
+        states::{LocalPurchaseState, OnlinePurchaseState},
+    };
+    use futures::future::join_all;
+    use proptest::prelude::*;
+    use tokio::{
+        io::{split, WriteHalf},
+        net::TcpStream,
+        sync::Mutex,
+    };
+
+    const PRODUCTS: [&str; 3] = ["A", "B", "C"];
+    const INITIAL_STOCK: u32 = 10;
+
+    /// What the ecom does with a reserved online purchase
+    #[derive(Debug, Clone)]
+    enum Outcome {
+        Commit,
+        Abort,
+        Cancel,
+        /// Nothing, so the shop releases it once the prepare timeout passes
+        Expire,
+    }
+
+    #[derive(Debug, Clone)]
+    enum Purchase {
+        /// Delivered by a supplier while the purchases go on
+        Restock {
+            product: &'static str,
+            quantity: u32,
+        },
+        Local {
+            product: &'static str,
+            quantity: u32,
+        },
+        Online {
+            product: &'static str,
+            quantity: u32,
+            outcome: Outcome,
+        },
+    }
+
+    /// Units of a product the shop gave away or got, as seen by the buyers and the supplier
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    enum Total {
+        Sold,
+        Committed,
+        Restocked,
+        /// Reported DELIVERED to the ecom
+        Delivered,
+        /// Reported LOST to the ecom
+        Lost,
+    }
+
+    fn purchase() -> impl Strategy<Value = (Purchase, u64)> {
+        let product = prop::sample::select(PRODUCTS.to_vec());
+        let outcome = prop_oneof![
+            Just(Outcome::Commit),
+            Just(Outcome::Abort),
+            Just(Outcome::Cancel),
+            Just(Outcome::Expire),
+        ];
+        let purchase = prop_oneof![
+            (product.clone(), 1u32..6)
+                .prop_map(|(product, quantity)| Purchase::Restock { product, quantity }),
+            (product.clone(), 1u32..6)
+                .prop_map(|(product, quantity)| Purchase::Local { product, quantity }),
+            (product, 1u32..6, outcome).prop_map(|(product, quantity, outcome)| {
+                Purchase::Online {
+                    product,
+                    quantity,
+                    outcome,
+                }
+            }),
+        ];
+        // cada compra empieza luego de una demora al azar, para variar el orden
+        (purchase, 0u64..5)
+    }
+
+    /// Bytes the shop sent to the ecom
+    type Received = Arc<std_sync::Mutex<Vec<u8>>>;
+
+    /// Connection to a peer that keeps everything the shop sends
+    fn ecom_connection() -> (Arc<Mutex<WriteHalf<TcpStream>>>, Received) {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let received = Received::default();
+        let peer_received = received.clone();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut bytes = [0u8; 1024];
+            while let Ok(read @ 1..) = stream.read(&mut bytes) {
+                peer_received
+                    .lock()
+                    .unwrap()
+                    .extend_from_slice(&bytes[..read]);
+            }
+        });
+        let stream = std::net::TcpStream::connect(address).unwrap();
+        stream.set_nonblocking(true).unwrap();
+        let (_read, write) = split(TcpStream::from_std(stream).unwrap());
+        (Arc::new(Mutex::new(write)), received)
+    }
+
+    /// Units of each product the shop reported to the ecom as delivered or lost
+    fn delivery_totals(received: &Received) -> HashMap<(Total, String), u32> {
+        let mut bytes = BytesMut::from(received.lock().unwrap().as_slice());
+        let mut codec = WireCodec::default();
+        let mut totals: HashMap<(Total, String), u32> = HashMap::new();
+        while let Ok(Some(message)) = codec.decode(&mut bytes) {
+            let (total, items) = match message {
+                WireMessage::OrderState {
+                    state: OnlinePurchaseState::DELIVERED,
+                    items,
+                    ..
+                } => (Total::Delivered, items),
+                WireMessage::OrderState {
+                    state: OnlinePurchaseState::LOST,
+                    items,
+                    ..
+                } => (Total::Lost, items),
+                _ => continue,
+            };
+            for item in items {
+                *totals.entry((total.clone(), item.product)).or_default() += item.quantity;
+            }
+        }
+        totals
+    }
+
+    /// Runs the purchase against the shop. Returns the item sold, committed or restocked by
+    /// it, if any
+    async fn run_purchase(
+        shop: Addr<Shop>,
+        write: Arc<Mutex<WriteHalf<TcpStream>>>,
+        (purchase, delay): (Purchase, u64),
+        sequence: u32,
+    ) -> Option<(Total, OrderItem)> {
+        clock::sleep(Duration::from_millis(delay)).await;
+        match purchase {
+            Purchase::Restock { product, quantity } => {
+                let items = vec![OrderItem::new(product, quantity)];
+                let restock = Restock {
+                    items,
+                    reorder: false,
+                };
+                let restocked = shop.send(restock).await.unwrap();
+                restocked
+                    .into_iter()
+                    .next()
+                    .map(|item| (Total::Restocked, item))
+            }
+            Purchase::Local { product, quantity } => {
+                let order = LocalPurchase {
+                    product: product.to_string(),
+                    quantity,
+                    status: LocalPurchaseState::CREATED,
+                };
+                let sold = matches!(
+                    shop.send(order).await.unwrap(),
+                    Ok(LocalPurchaseState::SOLD)
+                );
+                sold.then(|| (Total::Sold, OrderItem::new(product, quantity)))
+            }
+            Purchase::Online {
+                product,
+                quantity,
+                outcome,
+            } => {
+                let id = OrderId::new("ecom", sequence);
+                let purchase = OnlinePurchase {
+                    id: id.clone(),
+                    ecom: "ecom".to_string(),
+                    items: vec![OrderItem::new(product, quantity)],
+                    zone_id: 1,
+                    write: write.clone(),
+                    state: OnlinePurchaseState::RECEIVED,
+                    allow_partial: false,
+                };
+                let state = shop.send(purchase).await.unwrap();
+                if !matches!(state, Ok(OnlinePurchaseState::RESERVED)) {
+                    return None;
+                }
+                match outcome {
+                    Outcome::Commit => {
+                        shop.send(CommitPurchase { id }).await.unwrap();
+                        return Some((Total::Committed, OrderItem::new(product, quantity)));
+                    }
+                    Outcome::Abort => shop.send(AbortPurchase { id }).await.unwrap(),
+                    Outcome::Cancel => {
+                        let cancel = CancelPurchase {
+                            id,
+                            ecom: "ecom".to_string(),
+                            write,
+                        };
+                        let _ = shop.send(cancel).await.unwrap();
+                    }
+                    Outcome::Expire => {}
+                }
+                None
+            }
+        }
+    }
+
+    /// Runs every purchase at the same time against a new shop, and waits until the shop
+    /// holds no reservation and reported every committed purchase to the ecom. Returns the
+    /// units sold, committed, restocked, delivered and lost of each product, and the final
+    /// stock of the shop along with its ledger
+    async fn run_purchases(
+        purchases: Vec<(Purchase, u64)>,
+        shards: usize,
+        deliver_rate: f64,
+    ) -> (HashMap<(Total, String), u32>, Vec<(Product, ProductLedger)>) {
+        let stock = PRODUCTS
+            .iter()
+            .map(|id| Product {
+                id: id.to_string(),
+                stock: INITIAL_STOCK,
+                reserved: 0,
+            })
+            .collect();
+        let mut shop = Shop::new("tienda", "localhost:0", 1, stock);
+        shop.config = Config {
+            purchase_millis: 1,
+            prepare_timeout_millis: 100,
+            deliver_millis: 1..=5,
+            deliver_millis_per_distance: 0,
+            deliver_rate,
+            stock_shards: shards,
+            ..Config::default()
+        };
+        let shop = shop.start();
+        let (write, received) = ecom_connection();
+
+        let results = join_all(purchases.into_iter().enumerate().map(|(index, purchase)| {
+            run_purchase(shop.clone(), write.clone(), purchase, index as u32)
+        }))
+        .await;
+        let mut totals: HashMap<(Total, String), u32> = HashMap::new();
+        for (total, item) in results.into_iter().flatten() {
+            *totals.entry((total, item.product)).or_default() += item.quantity;
+        }
+
+        // las entregas y las reservas vencidas terminan luego de las compras
+        let committed: u32 = totals
+            .iter()
+            .filter(|((total, _), _)| *total == Total::Committed)
+            .map(|(_, quantity)| quantity)
+            .sum();
+        let start = Instant::now();
+        loop {
+            let ledger = shop.send(ReadLedger).await.unwrap();
+            let deliveries = delivery_totals(&received);
+            let settled = ledger.iter().all(|(product, _)| product.reserved == 0)
+                && deliveries.values().sum::<u32>() == committed;
+            if settled || start.elapsed() > Duration::from_secs(5) {
+                totals.extend(deliveries);
+                return (totals, ledger);
+            }
+            clock::sleep(Duration::from_millis(10)).await;
+        }
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(24))]
+
+        /// Whatever the order of the local and online purchases and the restocks, every unit
+        /// is in stock, sold or delivered (lost deliveries go back to the stock), as counted
+        /// by the buyers, the supplier and the ecom, and the ledger of the shop matches them
+        #[test]
+        fn test_stock_balances_after_any_interleaving(
+            purchases in prop::collection::vec(purchase(), 1..30),
+            shards in 1usize..4,
+            deliver_rate in 0.0f64..=1.0,
+        ) {
+            let system = System::new();
+            let (totals, ledger) = system.block_on(run_purchases(purchases, shards, deliver_rate));
+            let total = |total: Total, product: &str| {
+                u64::from(totals.get(&(total, product.to_string())).copied().unwrap_or(0))
+            };
+            System::current().stop();
+
+            prop_assert_eq!(ledger.len(), PRODUCTS.len());
+            for (product, ledger) in ledger {
+                let id = product.id.as_str();
+                prop_assert_eq!(product.reserved, 0);
+                prop_assert_eq!(
+                    u64::from(product.stock) + total(Total::Sold, id) + total(Total::Delivered, id),
+                    u64::from(INITIAL_STOCK) + total(Total::Restocked, id),
+                    "{:?}",
+                    product
+                );
+                prop_assert_eq!(
+                    total(Total::Delivered, id) + total(Total::Lost, id),
+                    total(Total::Committed, id)
+                );
+
+                prop_assert_eq!(ledger.initial, u64::from(INITIAL_STOCK));
+                prop_assert_eq!(ledger.sold, total(Total::Sold, id));
+                prop_assert_eq!(ledger.delivered, total(Total::Delivered, id));
+                prop_assert_eq!(ledger.lost, total(Total::Lost, id));
+                prop_assert_eq!(ledger.restocked, total(Total::Restocked, id));
+            }
+        }
+    }
+}